@@ -0,0 +1,337 @@
+use crate::{Command, Group, Merge};
+use alloc::boxed::Box;
+use core::{any::Any, fmt};
+
+/// Object safe version of [`Command`](trait.Command.html).
+///
+/// This trait is implemented for all commands that are `'static`,
+/// and is used by [`BoxedCommand`](struct.BoxedCommand.html) to store commands of different types
+/// in the same record or history.
+pub trait DynCommand<T, E>: Any {
+    /// Applies the command on the target.
+    fn dyn_apply(&mut self, target: &mut T) -> core::result::Result<(), E>;
+
+    /// Restores the state of the target as it was before the command was applied.
+    fn dyn_undo(&mut self, target: &mut T) -> core::result::Result<(), E>;
+
+    /// Reapplies the command on the target.
+    fn dyn_redo(&mut self, target: &mut T) -> core::result::Result<(), E>;
+
+    /// Merges the command with `command` if they have the same concrete type.
+    fn dyn_merge(&mut self, command: Box<dyn DynCommand<T, E>>)
+        -> Merge<Box<dyn DynCommand<T, E>>>;
+
+    /// Returns the approximate size of the command.
+    fn dyn_size_hint(&self) -> usize;
+
+    /// Returns the command as `&dyn Any`.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns the command as `&mut dyn Any`.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Returns the command as `Box<dyn Any>`.
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<C> DynCommand<C::Target, C::Error> for C
+where
+    C: Command + 'static,
+{
+    fn dyn_apply(&mut self, target: &mut C::Target) -> crate::Result<C> {
+        self.apply(target)
+    }
+
+    fn dyn_undo(&mut self, target: &mut C::Target) -> crate::Result<C> {
+        self.undo(target)
+    }
+
+    fn dyn_redo(&mut self, target: &mut C::Target) -> crate::Result<C> {
+        self.redo(target)
+    }
+
+    fn dyn_merge(
+        &mut self,
+        command: Box<dyn DynCommand<C::Target, C::Error>>,
+    ) -> Merge<Box<dyn DynCommand<C::Target, C::Error>>> {
+        // Only commands of the same concrete type can be merged.
+        if !command.as_any().is::<C>() {
+            return Merge::No(command);
+        }
+        let command = command.into_any().downcast::<C>().unwrap();
+        match self.merge(*command) {
+            Merge::Yes => Merge::Yes,
+            Merge::No(command) => Merge::No(Box::new(command)),
+            Merge::Annul => Merge::Annul,
+        }
+    }
+
+    fn dyn_size_hint(&self) -> usize {
+        self.size_hint()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// A boxed command that can hold commands of any type with the same target and error type.
+///
+/// Two boxed commands are merged if they contain the same concrete type
+/// and that type's [`merge`](trait.Command.html#method.merge) method merges them.
+/// The boxed command shows the text of the command if it was created using
+/// [`with_display`](struct.BoxedCommand.html#method.with_display), and nothing otherwise.
+///
+/// # Examples
+/// ```
+/// # use redo::{BoxedCommand, Command, Record};
+/// # struct Add(char);
+/// # impl Command for Add {
+/// #     type Target = String;
+/// #     type Error = &'static str;
+/// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         s.push(self.0);
+/// #         Ok(())
+/// #     }
+/// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         self.0 = s.pop().ok_or("s is empty")?;
+/// #         Ok(())
+/// #     }
+/// # }
+/// struct Clear(String);
+///
+/// impl Command for Clear {
+///     type Target = String;
+///     type Error = &'static str;
+///
+///     fn apply(&mut self, s: &mut String) -> redo::Result<Clear> {
+///         self.0 = core::mem::take(s);
+///         Ok(())
+///     }
+///
+///     fn undo(&mut self, s: &mut String) -> redo::Result<Clear> {
+///         *s = core::mem::take(&mut self.0);
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() -> Result<(), &'static str> {
+/// let mut record = Record::default();
+/// record.apply(BoxedCommand::new(Add('a')))?;
+/// record.apply(BoxedCommand::new(Add('b')))?;
+/// record.apply(BoxedCommand::new(Clear(String::new())))?;
+/// assert_eq!(record.target(), "");
+/// record.undo()?;
+/// assert_eq!(record.target(), "ab");
+/// # Ok(())
+/// # }
+/// ```
+pub struct BoxedCommand<T, E> {
+    command: Box<dyn DynCommand<T, E>>,
+    display: Option<fn(&dyn Any, &mut fmt::Formatter) -> fmt::Result>,
+}
+
+/// Formats the command, which is of type `C`.
+fn display<C: fmt::Display + 'static>(command: &dyn Any, f: &mut fmt::Formatter) -> fmt::Result {
+    command.downcast_ref::<C>().unwrap().fmt(f)
+}
+
+impl<T: 'static, E: 'static> BoxedCommand<T, E> {
+    /// Returns a new boxed command.
+    pub fn new<C>(command: C) -> BoxedCommand<T, E>
+    where
+        C: Command<Target = T, Error = E> + 'static,
+    {
+        BoxedCommand {
+            command: Box::new(command),
+            display: None,
+        }
+    }
+
+    /// Returns a new boxed command that shows the text of the command,
+    /// e.g. in [`undo_text`](record/struct.Record.html#method.undo_text).
+    pub fn with_display<C>(command: C) -> BoxedCommand<T, E>
+    where
+        C: Command<Target = T, Error = E> + fmt::Display + 'static,
+    {
+        BoxedCommand {
+            command: Box::new(command),
+            display: Some(display::<C>),
+        }
+    }
+
+    /// Returns `true` if the boxed command is of type `C`.
+    pub fn is<C: 'static>(&self) -> bool {
+        self.command.as_any().is::<C>()
+    }
+
+    /// Returns a reference to the command if it is of type `C`.
+    pub fn downcast_ref<C: 'static>(&self) -> Option<&C> {
+        self.command.as_any().downcast_ref()
+    }
+
+    /// Returns a mutable reference to the command if it is of type `C`.
+    pub fn downcast_mut<C: 'static>(&mut self) -> Option<&mut C> {
+        self.command.as_any_mut().downcast_mut()
+    }
+
+    /// Consumes the boxed command, returning the command if it is of type `C`.
+    pub fn downcast<C: 'static>(self) -> core::result::Result<C, BoxedCommand<T, E>> {
+        if self.is::<C>() {
+            Ok(*self.command.into_any().downcast().unwrap())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<T: 'static, E: 'static> Command for BoxedCommand<T, E> {
+    type Target = T;
+    type Error = E;
+
+    fn apply(&mut self, target: &mut T) -> crate::Result<Self> {
+        self.command.dyn_apply(target)
+    }
+
+    fn undo(&mut self, target: &mut T) -> crate::Result<Self> {
+        self.command.dyn_undo(target)
+    }
+
+    fn redo(&mut self, target: &mut T) -> crate::Result<Self> {
+        self.command.dyn_redo(target)
+    }
+
+    fn merge(&mut self, command: Self) -> Merge<Self> {
+        let BoxedCommand { command, display } = command;
+        match self.command.dyn_merge(command) {
+            Merge::Yes => Merge::Yes,
+            Merge::No(command) => Merge::No(BoxedCommand { command, display }),
+            Merge::Annul => Merge::Annul,
+        }
+    }
+
+    fn size_hint(&self) -> usize {
+        self.command.dyn_size_hint()
+    }
+}
+
+impl<T: 'static, E: 'static> From<Group<BoxedCommand<T, E>>> for BoxedCommand<T, E> {
+    fn from(group: Group<BoxedCommand<T, E>>) -> Self {
+        BoxedCommand::with_display(group)
+    }
+}
+
+impl<T, E> fmt::Debug for BoxedCommand<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("BoxedCommand { .. }")
+    }
+}
+
+impl<T: 'static, E: 'static> fmt::Display for BoxedCommand<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.display {
+            Some(display) => display(self.command.as_any(), f),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::string::String;
+    use core::fmt;
+
+    struct Add(char);
+
+    impl Command for Add {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Add> {
+            s.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Add> {
+            self.0 = s.pop().ok_or("s is empty")?;
+            Ok(())
+        }
+    }
+
+    impl fmt::Display for Add {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Add {}", self.0)
+        }
+    }
+
+    struct Push(String);
+
+    impl Command for Push {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Push> {
+            s.push_str(&self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Push> {
+            s.truncate(s.len() - self.0.len());
+            Ok(())
+        }
+
+        fn merge(&mut self, Push(s): Self) -> Merge<Self> {
+            self.0.push_str(&s);
+            Merge::Yes
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut record = Record::default();
+        record.apply(BoxedCommand::new(Add('a'))).unwrap();
+        record.apply(BoxedCommand::new(Push("b".into()))).unwrap();
+        record.apply(BoxedCommand::new(Push("c".into()))).unwrap();
+        record.apply(BoxedCommand::new(Add('d'))).unwrap();
+        assert_eq!(record.target(), "abcd");
+        assert_eq!(record.len(), 3);
+        record.undo().unwrap();
+        record.undo().unwrap();
+        assert_eq!(record.target(), "a");
+        record.redo().unwrap();
+        assert_eq!(record.target(), "abc");
+    }
+
+    #[test]
+    fn undo_text() {
+        let mut record = Record::default();
+        record.apply(BoxedCommand::with_display(Add('a'))).unwrap();
+        record.apply(BoxedCommand::new(Push("b".into()))).unwrap();
+        assert_eq!(record.undo_text().unwrap(), "");
+        record.undo().unwrap();
+        assert_eq!(record.undo_text().unwrap(), "Add a");
+        let mut cp = record.checkpoint();
+        cp.apply(BoxedCommand::with_display(Add('b'))).unwrap();
+        cp.apply(BoxedCommand::with_display(Add('c'))).unwrap();
+        cp.commit_squashed();
+        assert_eq!(record.undo_text().unwrap(), "Add b\nAdd c");
+    }
+
+    #[test]
+    fn downcast() {
+        let command = BoxedCommand::<String, &str>::new(Add('a'));
+        assert!(command.is::<Add>());
+        assert!(command.downcast_ref::<Push>().is_none());
+        let command = command.downcast::<Push>().err().unwrap();
+        assert_eq!(command.downcast::<Add>().unwrap().0, 'a');
+    }
+}
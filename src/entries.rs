@@ -0,0 +1,194 @@
+use crate::Entry;
+use alloc::collections::vec_deque;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use core::iter::{DoubleEndedIterator, ExactSizeIterator, FusedIterator};
+
+/// A read-only view of a command in a record or history.
+#[derive(Debug)]
+pub struct EntryRef<'a, C> {
+    index: usize,
+    entry: &'a Entry<C>,
+    current: usize,
+    saved: Option<usize>,
+}
+
+impl<'a, C> EntryRef<'a, C> {
+    pub(crate) fn new(
+        index: usize,
+        entry: &'a Entry<C>,
+        current: usize,
+        saved: Option<usize>,
+    ) -> EntryRef<'a, C> {
+        EntryRef {
+            index,
+            entry,
+            current,
+            saved,
+        }
+    }
+
+    /// Returns the index of the command.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the command.
+    pub fn command(&self) -> &'a C {
+        &self.entry.command
+    }
+
+    /// Returns the label of the command if it has one.
+    pub fn label(&self) -> Option<&'a str> {
+        self.entry.label.as_deref()
+    }
+
+    /// Returns the time the command was applied, or last merged into.
+    ///
+    /// Requires the `chrono` feature to be enabled.
+    #[cfg(feature = "chrono")]
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.entry.timestamp
+    }
+
+    /// Returns `true` if the command has been applied and can be undone.
+    pub fn is_applied(&self) -> bool {
+        self.index < self.current
+    }
+
+    /// Returns `true` if the command is the one that will be undone in the next call to `undo`.
+    pub fn is_current(&self) -> bool {
+        self.index + 1 == self.current
+    }
+
+    /// Returns `true` if the target is in a saved state right after the command.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.index + 1)
+    }
+}
+
+impl<C> Clone for EntryRef<'_, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for EntryRef<'_, C> {}
+
+/// Iterator over the commands in a record or history.
+///
+/// This struct is created by the [`entries`], [`undo_stack`] and [`redo_stack`] methods.
+///
+/// [`entries`]: record/struct.Record.html#method.entries
+/// [`undo_stack`]: record/struct.Record.html#method.undo_stack
+/// [`redo_stack`]: record/struct.Record.html#method.redo_stack
+#[derive(Debug)]
+pub struct Entries<'a, C> {
+    iter: vec_deque::Iter<'a, Entry<C>>,
+    front: usize,
+    back: usize,
+    current: usize,
+    saved: Option<usize>,
+}
+
+impl<'a, C> Entries<'a, C> {
+    pub(crate) fn new(
+        iter: vec_deque::Iter<'a, Entry<C>>,
+        front: usize,
+        current: usize,
+        saved: Option<usize>,
+    ) -> Entries<'a, C> {
+        Entries {
+            back: front + iter.len(),
+            iter,
+            front,
+            current,
+            saved,
+        }
+    }
+}
+
+impl<'a, C> Iterator for Entries<'a, C> {
+    type Item = EntryRef<'a, C>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.iter.next()?;
+        self.front += 1;
+        Some(EntryRef::new(
+            self.front - 1,
+            entry,
+            self.current,
+            self.saved,
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<C> DoubleEndedIterator for Entries<'_, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let entry = self.iter.next_back()?;
+        self.back -= 1;
+        Some(EntryRef::new(self.back, entry, self.current, self.saved))
+    }
+}
+
+impl<C> ExactSizeIterator for Entries<'_, C> {}
+
+impl<C> FusedIterator for Entries<'_, C> {}
+
+impl<C> Clone for Entries<'_, C> {
+    fn clone(&self) -> Self {
+        Entries {
+            iter: self.iter.clone(),
+            ..*self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::{string::String, vec::Vec};
+
+    struct Add(char);
+
+    impl Command for Add {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Add> {
+            s.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Add> {
+            self.0 = s.pop().ok_or("s is empty")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn entries() {
+        let mut record = Record::default();
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        record.set_saved(true);
+        record.apply(Add('c')).unwrap();
+        record.undo().unwrap();
+        let chars: Vec<_> = record.entries().rev().map(|e| e.command().0).collect();
+        assert_eq!(chars, ['c', 'b', 'a']);
+        let b = record.get(1).unwrap();
+        assert!(b.is_applied() && b.is_current() && b.is_saved());
+        assert!(!record.get(2).unwrap().is_applied());
+        assert!(record.get(3).is_none());
+        let undo: Vec<_> = record.undo_stack().map(|e| e.index()).collect();
+        assert_eq!(undo, [0, 1]);
+        let mut redo = record.redo_stack();
+        assert_eq!(redo.len(), 1);
+        assert_eq!(redo.next_back().unwrap().index(), 2);
+        assert!(redo.next().is_none());
+    }
+}
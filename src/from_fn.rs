@@ -0,0 +1,155 @@
+use crate::Command;
+use alloc::string::String;
+use core::{fmt, marker::PhantomData};
+
+/// A command made from closures.
+///
+/// The `apply` closure is used for redo unless a separate redo closure is provided.
+///
+/// # Examples
+/// ```
+/// # use redo::{command_fn, Record};
+/// # fn main() -> Result<(), &'static str> {
+/// let mut record = Record::default();
+/// let add = command_fn(
+///     |s: &mut String| {
+///         s.push('a');
+///         Ok(())
+///     },
+///     |s: &mut String| s.pop().map(|_| ()).ok_or("s is empty"),
+/// )
+/// .with_text("Add 'a'");
+/// record.apply(add)?;
+/// assert_eq!(record.target(), "a");
+/// assert_eq!(record.undo_text().unwrap(), "Add 'a'");
+/// record.undo()?;
+/// assert_eq!(record.target(), "");
+/// # Ok(())
+/// # }
+/// ```
+pub struct FromFn<T, E, A, U, R = fn(&mut T) -> core::result::Result<(), E>> {
+    apply: A,
+    undo: U,
+    redo: Option<R>,
+    text: Option<String>,
+    target: PhantomData<fn(&mut T) -> E>,
+}
+
+impl<T, E, A, U> FromFn<T, E, A, U>
+where
+    A: FnMut(&mut T) -> core::result::Result<(), E>,
+    U: FnMut(&mut T) -> core::result::Result<(), E>,
+{
+    /// Returns a new command made from the `apply` and `undo` closures.
+    pub fn new(apply: A, undo: U) -> Self {
+        FromFn {
+            apply,
+            undo,
+            redo: None,
+            text: None,
+            target: PhantomData,
+        }
+    }
+}
+
+impl<T, E, A, U, R> FromFn<T, E, A, U, R> {
+    /// Sets the closure used when the command is redone.
+    pub fn with_redo<S>(self, redo: S) -> FromFn<T, E, A, U, S>
+    where
+        S: FnMut(&mut T) -> core::result::Result<(), E>,
+    {
+        FromFn {
+            apply: self.apply,
+            undo: self.undo,
+            redo: Some(redo),
+            text: self.text,
+            target: PhantomData,
+        }
+    }
+
+    /// Sets the text shown when the command is displayed.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+}
+
+impl<T, E, A, U, R> Command for FromFn<T, E, A, U, R>
+where
+    A: FnMut(&mut T) -> core::result::Result<(), E>,
+    U: FnMut(&mut T) -> core::result::Result<(), E>,
+    R: FnMut(&mut T) -> core::result::Result<(), E>,
+{
+    type Target = T;
+    type Error = E;
+
+    fn apply(&mut self, target: &mut T) -> crate::Result<Self> {
+        (self.apply)(target)
+    }
+
+    fn undo(&mut self, target: &mut T) -> crate::Result<Self> {
+        (self.undo)(target)
+    }
+
+    fn redo(&mut self, target: &mut T) -> crate::Result<Self> {
+        match self.redo {
+            Some(ref mut redo) => redo(target),
+            None => (self.apply)(target),
+        }
+    }
+}
+
+impl<T, E, A, U, R> fmt::Debug for FromFn<T, E, A, U, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FromFn").field("text", &self.text).finish()
+    }
+}
+
+impl<T, E, A, U, R> fmt::Display for FromFn<T, E, A, U, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.text {
+            Some(ref text) => f.write_str(text),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns a command made from the `apply` and `undo` closures.
+///
+/// See [`FromFn`](struct.FromFn.html) for more information.
+pub fn command_fn<T, E, A, U>(apply: A, undo: U) -> FromFn<T, E, A, U>
+where
+    A: FnMut(&mut T) -> core::result::Result<(), E>,
+    U: FnMut(&mut T) -> core::result::Result<(), E>,
+{
+    FromFn::new(apply, undo)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::string::String;
+
+    #[test]
+    fn redo() {
+        let mut record = Record::default();
+        let command = command_fn(
+            |s: &mut String| {
+                s.push('a');
+                Ok(())
+            },
+            |s: &mut String| s.pop().map(|_| ()).ok_or("s is empty"),
+        )
+        .with_redo(|s: &mut String| {
+            s.push('b');
+            Ok(())
+        });
+        record.apply(command).unwrap();
+        assert_eq!(record.target(), "a");
+        record.undo().unwrap();
+        assert_eq!(record.target(), "");
+        record.redo().unwrap();
+        assert_eq!(record.target(), "b");
+        assert_eq!(record.undo_text().unwrap(), "");
+    }
+}
@@ -0,0 +1,225 @@
+use crate::Command;
+use alloc::vec::{self, Vec};
+use core::{fmt, iter::FromIterator, slice};
+
+/// A group of commands that is applied, undone, and redone as a single command.
+///
+/// The commands are applied in order and undone in reverse order.
+/// If one of the commands fail, the commands in the group that have already been executed
+/// are rolled back before the error is returned, leaving the target as it was before the call.
+/// Errors that occur during the rollback are ignored.
+///
+/// # Examples
+/// ```
+/// # use redo::{Command, Group, Record};
+/// # struct Add(char);
+/// # impl Command for Add {
+/// #     type Target = String;
+/// #     type Error = &'static str;
+/// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         s.push(self.0);
+/// #         Ok(())
+/// #     }
+/// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         self.0 = s.pop().ok_or("s is empty")?;
+/// #         Ok(())
+/// #     }
+/// # }
+/// # fn main() -> redo::Result<Add> {
+/// let mut record = Record::default();
+/// let mut group = Group::new();
+/// group.push(Add('a'));
+/// group.push(Add('b'));
+/// group.push(Add('c'));
+/// record.apply(group)?;
+/// assert_eq!(record.target(), "abc");
+/// assert_eq!(record.len(), 1);
+/// record.undo()?;
+/// assert_eq!(record.target(), "");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Group<C> {
+    commands: Vec<C>,
+}
+
+impl<C> Group<C> {
+    /// Returns a new empty group.
+    pub fn new() -> Group<C> {
+        Group {
+            commands: Vec::new(),
+        }
+    }
+
+    /// Adds the command to the end of the group.
+    pub fn push(&mut self, command: C) {
+        self.commands.push(command);
+    }
+
+    /// Returns the number of commands in the group.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns `true` if the group is empty.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Returns an iterator over the commands in the group.
+    pub fn iter(&self) -> slice::Iter<'_, C> {
+        self.commands.iter()
+    }
+
+    /// Consumes the group, returning the commands.
+    pub fn into_commands(self) -> Vec<C> {
+        self.commands
+    }
+}
+
+impl<C: Command> Command for Group<C> {
+    type Target = C::Target;
+    type Error = C::Error;
+
+    fn apply(&mut self, target: &mut C::Target) -> crate::Result<Self> {
+        for i in 0..self.commands.len() {
+            if let Err(err) = self.commands[i].apply(target) {
+                for command in self.commands[..i].iter_mut().rev() {
+                    let _ = command.undo(target);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, target: &mut C::Target) -> crate::Result<Self> {
+        for i in (0..self.commands.len()).rev() {
+            if let Err(err) = self.commands[i].undo(target) {
+                for command in &mut self.commands[i + 1..] {
+                    let _ = command.redo(target);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn redo(&mut self, target: &mut C::Target) -> crate::Result<Self> {
+        for i in 0..self.commands.len() {
+            if let Err(err) = self.commands[i].redo(target) {
+                for command in self.commands[..i].iter_mut().rev() {
+                    let _ = command.undo(target);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        self.commands.iter().map(C::size_hint).sum()
+    }
+}
+
+impl<C> Default for Group<C> {
+    fn default() -> Self {
+        Group::new()
+    }
+}
+
+impl<C> From<Vec<C>> for Group<C> {
+    fn from(commands: Vec<C>) -> Self {
+        Group { commands }
+    }
+}
+
+impl<C> FromIterator<C> for Group<C> {
+    fn from_iter<I: IntoIterator<Item = C>>(iter: I) -> Self {
+        Group {
+            commands: Vec::from_iter(iter),
+        }
+    }
+}
+
+impl<C> Extend<C> for Group<C> {
+    fn extend<I: IntoIterator<Item = C>>(&mut self, iter: I) {
+        self.commands.extend(iter);
+    }
+}
+
+impl<C> IntoIterator for Group<C> {
+    type Item = C;
+    type IntoIter = vec::IntoIter<C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.commands.into_iter()
+    }
+}
+
+impl<'a, C> IntoIterator for &'a Group<C> {
+    type Item = &'a C;
+    type IntoIter = slice::Iter<'a, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.commands.iter()
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for Group<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, command) in self.commands.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            (command as &dyn fmt::Display).fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::{string::String, vec};
+
+    struct Add(char);
+
+    impl Command for Add {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Add> {
+            if self.0 == '!' {
+                return Err("invalid char");
+            }
+            s.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Add> {
+            self.0 = s.pop().ok_or("s is empty")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rollback() {
+        let mut record = Record::default();
+        record.apply(Group::from(vec![Add('a')])).unwrap();
+        let group = vec![Add('b'), Add('c'), Add('!'), Add('d')]
+            .into_iter()
+            .collect::<Group<_>>();
+        assert_eq!(record.apply(group), Err("invalid char"));
+        assert_eq!(record.target(), "a");
+        assert_eq!(record.len(), 1);
+        let group = Group::from(vec![Add('b'), Add('c')]);
+        record.apply(group).unwrap();
+        assert_eq!(record.target(), "abc");
+        record.undo().unwrap();
+        assert_eq!(record.target(), "a");
+        record.redo().unwrap();
+        assert_eq!(record.target(), "abc");
+    }
+}
@@ -1,15 +1,22 @@
 //! A history of commands.
 
-use crate::{format::Format, At, Command, Entry, Record, Result, Signal};
+use crate::{
+    format::Format, record::Applied, At, Command, Entries, Entry, EntryRef, Group, Observer,
+    QueueError, Record, Result, Signal, Subscription,
+};
 use alloc::{
+    boxed::Box,
     collections::{BTreeMap, VecDeque},
     string::{String, ToString},
     vec,
     vec::Vec,
 };
 #[cfg(feature = "chrono")]
-use chrono::{DateTime, TimeZone};
-use core::fmt::{self, Write};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use core::{
+    fmt::{self, Write},
+    mem,
+};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +25,9 @@ use serde::{Deserialize, Serialize};
 /// Unlike [Record](struct.Record.html) which maintains a linear undo history, History maintains an undo tree
 /// containing every edit made to the target.
 ///
+/// The history is `Send` if the commands, the target, and the slots are `Send`, and `Sync` if they are `Sync`.
+/// Use [`SharedHistory`](../struct.SharedHistory.html) to share a history between threads.
+///
 /// # Examples
 /// ```
 /// # use redo::{Command, History};
@@ -61,6 +71,8 @@ pub struct History<C: Command, F = fn(Signal)> {
     root: usize,
     next: usize,
     pub(crate) saved: Option<At>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) bookmarks: BTreeMap<String, At>,
     pub(crate) record: Record<C, F>,
     pub(crate) branches: BTreeMap<usize, Branch<C>>,
 }
@@ -74,6 +86,7 @@ where
             root: self.root,
             next: self.next,
             saved: self.saved,
+            bookmarks: self.bookmarks.clone(),
             record: self.record.clone(),
             branches: self.branches.clone(),
         }
@@ -121,16 +134,45 @@ impl<C: Command, F> History<C, F> {
         self.record.limit()
     }
 
-    /// Sets how the signal should be handled when the state changes.
+    /// Returns the budget of the history if it has one.
+    pub fn budget(&self) -> Option<usize> {
+        self.record.budget()
+    }
+
+    /// Returns the total size of the commands in the current branch.
+    ///
+    /// The commands in the other branches are not included.
+    /// See [`size_hint`](../trait.Command.html#method.size_hint) for more information.
+    pub fn size(&self) -> usize {
+        self.record.size()
+    }
+
+    /// Connects a slot that is called with the signal when the state changes.
     ///
-    /// The previous slot is returned if it exists.
-    pub fn connect(&mut self, slot: F) -> Option<F> {
+    /// All connected slots are called in the order they were connected.
+    /// The returned subscription can be used to disconnect the slot.
+    pub fn connect(&mut self, slot: F) -> Subscription {
         self.record.connect(slot)
     }
 
-    /// Removes and returns the slot if it exists.
-    pub fn disconnect(&mut self) -> Option<F> {
-        self.record.disconnect()
+    /// Removes and returns the slot with the subscription if it exists.
+    pub fn disconnect(&mut self, subscription: Subscription) -> Option<F> {
+        self.record.disconnect(subscription)
+    }
+
+    /// Replaces the slots, returning the history with the new slots and the old slots.
+    #[cfg(feature = "std")]
+    pub(crate) fn replace_slot<G>(self, slot: crate::Slot<G>) -> (History<C, G>, crate::Slot<F>) {
+        let (record, slot) = self.record.replace_slot(slot);
+        let history = History {
+            root: self.root,
+            next: self.next,
+            saved: self.saved,
+            bookmarks: self.bookmarks,
+            record,
+            branches: self.branches,
+        };
+        (history, slot)
     }
 
     /// Returns `true` if the target is in a saved state, `false` otherwise.
@@ -158,18 +200,138 @@ impl<C: Command, F> History<C, F> {
         self.record.current()
     }
 
-    /// Returns a queue.
-    pub fn queue(&mut self) -> Queue<C, F> {
-        Queue::from(self)
+    /// Returns the position of the bookmark if it exists.
+    pub fn bookmark(&self, name: &str) -> Option<At> {
+        match self.record.bookmark(name) {
+            Some(current) => Some(At::new(self.branch(), current)),
+            None => self.bookmarks.get(name).copied(),
+        }
     }
 
-    /// Returns a checkpoint.
-    pub fn checkpoint(&mut self) -> Checkpoint<C, F> {
-        Checkpoint::from(self)
+    /// Returns `true` if the target is at the bookmark, `false` otherwise.
+    pub fn is_at_bookmark(&self, name: &str) -> bool {
+        self.record.is_at_bookmark(name)
+    }
+
+    /// Returns an iterator over the names and positions of the bookmarks.
+    ///
+    /// The bookmarks in the current branch come first, then the bookmarks in the other branches.
+    pub fn bookmarks(&self) -> impl Iterator<Item = (&str, At)> {
+        let root = self.branch();
+        self.record
+            .bookmarks()
+            .map(move |(name, current)| (name, At::new(root, current)))
+            .chain(self.bookmarks.iter().map(|(name, &at)| (name.as_str(), at)))
+    }
+
+    /// Returns an iterator over the branches that are not the current branch, ordered by id.
+    pub fn branches(&self) -> impl Iterator<Item = BranchRef<'_, C>> {
+        self.branches
+            .iter()
+            .map(move |(&id, branch)| self.branch_ref(id, branch))
+    }
+
+    /// Returns the branch with the id if it exists and is not the current branch.
+    pub fn get_branch(&self, id: usize) -> Option<BranchRef<'_, C>> {
+        self.branches
+            .get(&id)
+            .map(|branch| self.branch_ref(id, branch))
+    }
+
+    /// Returns an iterator over the ids of the branches that fork from the position.
+    pub fn children(&self, at: At) -> impl Iterator<Item = usize> + '_ {
+        self.branches
+            .iter()
+            .filter(move |(_, child)| child.parent == at)
+            .map(|(&id, _)| id)
+    }
+
+    fn branch_ref<'a>(&self, id: usize, branch: &'a Branch<C>) -> BranchRef<'a, C> {
+        BranchRef {
+            id,
+            branch,
+            saved: self
+                .saved
+                .filter(|saved| saved.branch == id)
+                .map(|saved| saved.current),
+        }
+    }
+
+    /// Returns an iterator over all the commands in the history,
+    /// together with the positions of the states right after them.
+    fn all_entries(&self) -> impl Iterator<Item = (At, &Entry<C>)> {
+        let root = self.branch();
+        let record = self
+            .record
+            .entries
+            .iter()
+            .enumerate()
+            .map(move |(i, entry)| (At::new(root, i + 1), entry));
+        let branches = self.branches.iter().flat_map(|(&id, branch)| {
+            let parent = branch.parent.current;
+            branch
+                .entries
+                .iter()
+                .enumerate()
+                .map(move |(i, entry)| (At::new(id, parent + i + 1), entry))
+        });
+        record.chain(branches)
+    }
+
+    /// Returns the positions of all the states in the history ordered by when they were created,
+    /// and the index of the current state.
+    fn states(&self) -> (Vec<At>, usize) {
+        let mut states = vec![(0, At::new(self.branch(), 0))];
+        states.extend(self.all_entries().map(|(at, entry)| (entry.seq, at)));
+        states.sort_unstable();
+        let current = self.position();
+        let i = states.iter().position(|&(_, at)| at == current).unwrap();
+        (states.into_iter().map(|(_, at)| at).collect(), i)
+    }
+
+    /// Returns the command at `index` in the current branch if it exists.
+    pub fn get(&self, index: usize) -> Option<EntryRef<'_, C>> {
+        self.record.get(index)
+    }
+
+    /// Returns an iterator over the commands in the current branch, starting with the oldest.
+    pub fn entries(&self) -> Entries<'_, C> {
+        self.record.entries()
+    }
+
+    /// Returns an iterator over the commands that can be undone, starting with the oldest.
+    pub fn undo_stack(&self) -> Entries<'_, C> {
+        self.record.undo_stack()
+    }
+
+    /// Returns an iterator over the commands that can be redone,
+    /// starting with the one that will be redone in the next call to `redo`.
+    pub fn redo_stack(&self) -> Entries<'_, C> {
+        self.record.redo_stack()
+    }
+
+    /// Marks the current command as closed to merging.
+    ///
+    /// Commands applied after this will not be merged into it,
+    /// even if it is undone and redone or moved to another branch later.
+    pub fn seal(&mut self) {
+        self.record.seal();
+    }
+
+    /// Prevents the next command from being merged into the current command.
+    ///
+    /// This is the same as [`seal`](struct.History.html#method.seal).
+    pub fn break_merge(&mut self) {
+        self.record.break_merge();
+    }
+
+    /// Returns a queue.
+    pub fn queue(&mut self) -> Queue<'_, C, F> {
+        Queue::from(self)
     }
 
     /// Returns a structure for configurable formatting of the history.
-    pub fn display(&self) -> Display<C, F> {
+    pub fn display(&self) -> Display<'_, C, F> {
         Display::from(self)
     }
 
@@ -190,23 +352,76 @@ impl<C: Command, F> History<C, F> {
         self.record.into_target()
     }
 
-    fn at(&self) -> At {
+    /// Returns the position of the current command in the history tree.
+    pub fn position(&self) -> At {
         At::new(self.branch(), self.current())
     }
+
+    /// Returns the position of the saved state if there is one.
+    pub fn saved(&self) -> Option<At> {
+        match self.record.saved {
+            Some(saved) => Some(At::new(self.branch(), saved)),
+            None => self.saved,
+        }
+    }
 }
 
-impl<C: Command, F: FnMut(Signal)> History<C, F> {
+impl<C: Command, F: Observer> History<C, F> {
+    /// Returns a checkpoint.
+    ///
+    /// The changes made through the checkpoint are canceled if it is dropped without being committed.
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, C, F> {
+        Checkpoint::from(self)
+    }
+
+    /// Returns a checkpoint with the label.
+    ///
+    /// When committed with [`commit_squashed`] the changes are stored as a single entry with the label,
+    /// which is shown by [`undo_text`], [`redo_text`], and the display structure.
+    /// When committed with [`commit`] the label is given to the last command applied through the checkpoint.
+    /// The labeled entry is sealed, so later commands are not merged into it.
+    ///
+    /// [`commit`]: history/struct.Checkpoint.html#method.commit
+    /// [`commit_squashed`]: history/struct.Checkpoint.html#method.commit_squashed
+    /// [`undo_text`]: struct.History.html#method.undo_text
+    /// [`redo_text`]: struct.History.html#method.redo_text
+    pub fn transaction(&mut self, label: impl Into<String>) -> Checkpoint<'_, C, F> {
+        let mut checkpoint = Checkpoint::from(self);
+        checkpoint.set_label(label);
+        checkpoint
+    }
+
     /// Marks the target as currently being in a saved or unsaved state.
     pub fn set_saved(&mut self, saved: bool) {
         self.saved = None;
         self.record.set_saved(saved);
     }
 
+    /// Marks the current position with a bookmark named `name`.
+    ///
+    /// If the bookmark already exists it is moved to the current position,
+    /// even if it is in another branch.
+    /// Like the saved state, commands will not be merged into the command at a bookmark.
+    pub fn set_bookmark(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        self.bookmarks.remove(&name);
+        self.record.set_bookmark(name);
+    }
+
+    /// Removes the bookmark and returns its position if it existed.
+    pub fn remove_bookmark(&mut self, name: &str) -> Option<At> {
+        match self.record.remove_bookmark(name) {
+            Some(current) => Some(At::new(self.branch(), current)),
+            None => self.bookmarks.remove(name),
+        }
+    }
+
     /// Removes all commands from the history without undoing them.
     pub fn clear(&mut self) {
         self.root = 0;
         self.next = 1;
         self.saved = None;
+        self.bookmarks.clear();
         self.record.clear();
         self.branches.clear();
     }
@@ -218,27 +433,92 @@ impl<C: Command, F: FnMut(Signal)> History<C, F> {
     ///
     /// [`apply`]: trait.Command.html#tymethod.apply
     pub fn apply(&mut self, command: C) -> Result<C> {
-        let at = self.at();
+        self.__apply(command, true).map(|_| ())
+    }
+
+    /// Applies the command, merging it into the current command only if `merge` is `true`.
+    pub(crate) fn __apply(
+        &mut self,
+        command: C,
+        merge: bool,
+    ) -> core::result::Result<(bool, usize), C::Error> {
+        let at = self.position();
         let saved = self.record.saved.filter(|&saved| saved > at.current);
-        let (merged, tail) = self.record.__apply(command)?;
-        // Check if the limit has been reached.
-        if !merged && at.current == self.current() {
-            let root = self.branch();
-            self.rm_child(root, 0);
-            self.branches
-                .values_mut()
-                .filter(|branch| branch.parent.branch == root)
-                .for_each(|branch| branch.parent.current -= 1);
-        }
+        let bookmarks = self.tail_bookmarks(at.current);
+        let Applied {
+            merged,
+            evicted,
+            tail,
+        } = self.record.__apply(command, merge)?;
         // Handle new branch.
         if !tail.is_empty() {
             let new = self.next;
             self.next += 1;
             self.branches
                 .insert(at.branch, Branch::new(new, at.current, tail));
-            self.set_root(new, at.current, saved);
+            self.set_root(new, at.current, saved, bookmarks);
+        }
+        // Check if the limit, retention or budget has been exceeded.
+        if evicted > 0 {
+            self.rm_evicted(evicted);
+        }
+        Ok((merged, evicted))
+    }
+
+    /// Removes the branches that fork before the `n` first commands in the root branch
+    /// after they have been removed, and shifts the positions of the rest.
+    fn rm_evicted(&mut self, n: usize) {
+        let root = self.branch();
+        for i in 0..n {
+            self.rm_child(root, i);
+        }
+        self.shift(n, n);
+    }
+
+    /// Collapses the commands between `from` and the current position into a single entry.
+    ///
+    /// Branches that fork between the collapsed commands are removed.
+    pub(crate) fn squash(&mut self, from: usize, label: Option<String>)
+    where
+        C: From<Group<C>>,
+    {
+        let root = self.branch();
+        let current = self.current();
+        for i in from + 1..current {
+            self.rm_child(root, i);
+        }
+        self.record.squash(from, label);
+        let removed = current - self.current();
+        if removed > 0 {
+            self.shift(current, removed);
+        }
+    }
+
+    /// Moves the branches that fork from the root branch at or after `from`,
+    /// and the branches that fork from them, `n` positions back.
+    fn shift(&mut self, from: usize, n: usize) {
+        let root = self.branch();
+        let shifted: Vec<_> = self
+            .branches
+            .keys()
+            .copied()
+            .filter(|&id| {
+                let mut parent = self.branches[&id].parent;
+                while parent.branch != root {
+                    parent = self.branches[&parent.branch].parent;
+                }
+                parent.current >= from
+            })
+            .collect();
+        for id in &shifted {
+            self.branches.get_mut(id).unwrap().parent.current -= n;
+        }
+        let saved = self.saved.iter_mut();
+        for at in saved.chain(self.bookmarks.values_mut()) {
+            if shifted.contains(&at.branch) {
+                at.current -= n;
+            }
         }
-        Ok(())
     }
 
     /// Calls the [`undo`] method for the active command
@@ -275,84 +555,257 @@ impl<C: Command, F: FnMut(Signal)> History<C, F> {
         if root == branch {
             return self.record.go_to(current);
         }
-        // Walk the path from `root` to `branch`.
-        for (new, branch) in self.mk_path(branch)? {
-            // Walk to `branch.current` either by undoing or redoing.
-            if let Err(err) = self.record.go_to(branch.parent.current).unwrap() {
-                return Some(Err(err));
-            }
-            // Apply the commands in the branch and move older commands into their own branch.
-            for entry in branch.entries {
-                let current = self.current();
-                let saved = self.record.saved.filter(|&saved| saved > current);
-                let entries = match self.record.__apply(entry.command) {
-                    Ok((_, entries)) => entries,
-                    Err(err) => return Some(Err(err)),
-                };
-                if !entries.is_empty() {
-                    self.branches
-                        .insert(self.root, Branch::new(new, current, entries));
-                    self.set_root(new, current, saved);
+        let path = self.mk_path(branch)?;
+        self.batch(|history| {
+            // Walk the path from `root` to `branch`.
+            for id in path {
+                // Walk to the position the branch forks from, either by undoing or redoing.
+                let parent = history.branches[&id].parent.current;
+                if let Err(err) = history.record.go_to(parent).unwrap() {
+                    return Some(Err(err));
                 }
+                // Move the commands in the branch into the root branch.
+                history.jump_to(id);
             }
-        }
-        self.record.go_to(current)
+            history.record.go_to(current)
+        })
+    }
+
+    /// Calls `f` without emitting any signals, and then emits the commands that were evicted
+    /// and applied, and the net changes to the branch, undo, redo, saved and bookmark states.
+    fn batch<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let old = self.branch();
+        let batch = self.record.begin_batch();
+        let t = f(self);
+        self.record.end_batch(batch);
+        let new = self.branch();
+        self.record
+            .emit_if(old != new, Signal::BranchChanged { old, new });
+        t
+    }
+
+    /// Same as [`go_to`](struct.History.html#method.go_to), but takes the position as an [`At`].
+    ///
+    /// [`At`]: ../struct.At.html
+    pub fn go_to_at(&mut self, at: At) -> Option<Result<C>> {
+        self.go_to(at.branch, at.current)
+    }
+
+    /// Revert the changes done to the target since the saved state.
+    ///
+    /// The saved state can be in any branch of the history.
+    pub fn revert(&mut self) -> Option<Result<C>> {
+        self.saved().and_then(|at| self.go_to_at(at))
+    }
+
+    /// Goes to the bookmark with the name, see [`go_to`](struct.History.html#method.go_to).
+    ///
+    /// Returns `None` if there is no bookmark with the name.
+    pub fn go_to_bookmark(&mut self, name: &str) -> Option<Result<C>> {
+        self.bookmark(name).and_then(|at| self.go_to_at(at))
+    }
+
+    /// Goes back `n` states in the order they were created, regardless of which branch they are in.
+    ///
+    /// This works like `g-` in Vim. The state before the first command counts as the earliest state.
+    ///
+    /// # Errors
+    /// If an error occur when executing [`undo`] or [`redo`] the error is returned.
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    /// [`redo`]: trait.Command.html#method.redo
+    pub fn earlier(&mut self, n: usize) -> Result<C> {
+        let (states, i) = self.states();
+        self.go_to_at(states[i.saturating_sub(n)]).unwrap()
+    }
+
+    /// Goes forward `n` states in the order they were created, regardless of which branch they are in.
+    ///
+    /// This works like `g+` in Vim.
+    ///
+    /// # Errors
+    /// If an error occur when executing [`undo`] or [`redo`] the error is returned.
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    /// [`redo`]: trait.Command.html#method.redo
+    pub fn later(&mut self, n: usize) -> Result<C> {
+        let (states, i) = self.states();
+        let j = i.saturating_add(n).min(states.len() - 1);
+        self.go_to_at(states[j]).unwrap()
     }
 
     /// Go back or forward in the history to the command that was made closest to the datetime provided.
     ///
-    /// This method does not jump across branches.
+    /// All the branches are searched, and the history moves to the state right after
+    /// the last command made before `to`, switching branch if needed.
+    /// Like in [`Record::time_travel`], a command made exactly at `to` is not included,
+    /// so the history moves to the state before it.
+    /// If several commands were made at the same time, the one that was applied last is chosen.
+    /// If no command was made before `to`, the history moves to the start of the current branch.
+    ///
+    /// [`Record::time_travel`]: ../record/struct.Record.html#method.time_travel
     #[cfg(feature = "chrono")]
     pub fn time_travel(&mut self, to: &DateTime<impl TimeZone>) -> Option<Result<C>> {
-        self.record.time_travel(to)
+        let at = self.find_time(to.with_timezone(&Utc), false)?;
+        self.go_to_at(at)
+    }
+
+    /// Goes back in the history by `duration`, measured from the time stamp of the current command,
+    /// and returns the number of commands that were undone and redone.
+    ///
+    /// All the branches are searched the same way as in [`time_travel`],
+    /// except that a command made exactly at the time is included.
+    /// If no command has been applied, the duration is measured from the oldest command in the current branch.
+    ///
+    /// # Errors
+    /// If an error occur when executing [`undo`] or [`redo`] the error is returned.
+    ///
+    /// [`time_travel`]: struct.History.html#method.time_travel
+    /// [`undo`]: ../trait.Command.html#tymethod.undo
+    /// [`redo`]: ../trait.Command.html#method.redo
+    #[cfg(feature = "chrono")]
+    pub fn travel_back(&mut self, duration: Duration) -> core::result::Result<usize, C::Error> {
+        match self.record.timestamp() {
+            Some(timestamp) => self.travel(timestamp - duration),
+            None => Ok(0),
+        }
+    }
+
+    /// Goes forward in the history by `duration`, measured from the time stamp of the current command,
+    /// and returns the number of commands that were undone and redone.
+    ///
+    /// All the branches are searched the same way as in [`time_travel`],
+    /// except that a command made exactly at the time is included.
+    /// If no command has been applied, the duration is measured from the oldest command in the current branch.
+    ///
+    /// # Errors
+    /// If an error occur when executing [`undo`] or [`redo`] the error is returned.
+    ///
+    /// [`time_travel`]: struct.History.html#method.time_travel
+    /// [`undo`]: ../trait.Command.html#tymethod.undo
+    /// [`redo`]: ../trait.Command.html#method.redo
+    #[cfg(feature = "chrono")]
+    pub fn travel_forward(&mut self, duration: Duration) -> core::result::Result<usize, C::Error> {
+        match self.record.timestamp() {
+            Some(timestamp) => self.travel(timestamp + duration),
+            None => Ok(0),
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    fn travel(&mut self, to: DateTime<Utc>) -> core::result::Result<usize, C::Error> {
+        let at = self.find_time(to, true).unwrap();
+        let steps = self.distance(at);
+        self.go_to_at(at).unwrap()?;
+        Ok(steps)
+    }
+
+    /// Returns the position of the state right after the last command made before `to`,
+    /// or at `to` if `inclusive` is `true`.
+    #[cfg(feature = "chrono")]
+    fn find_time(&self, to: DateTime<Utc>, inclusive: bool) -> Option<At> {
+        let mut entries = self.all_entries().peekable();
+        entries.peek()?;
+        let at = entries
+            .filter(|(_, entry)| entry.timestamp < to || inclusive && entry.timestamp == to)
+            .max_by_key(|&(at, entry)| (entry.timestamp, entry.seq, at))
+            .map_or(At::new(self.branch(), 0), |(at, _)| at);
+        Some(at)
+    }
+
+    /// Returns the number of commands that are undone and redone when going to `at`.
+    #[cfg(feature = "chrono")]
+    fn distance(&self, at: At) -> usize {
+        let mut current = self.current();
+        let mut steps = 0;
+        if at.branch != self.branch() {
+            for id in self.mk_path(at.branch).unwrap() {
+                let parent = self.branches[&id].parent.current;
+                steps += current.abs_diff(parent);
+                current = parent;
+            }
+        }
+        steps + current.abs_diff(at.current)
+    }
+
+    /// Removes the commands in the current branch that can be undone and were made before `time`,
+    /// together with the branches that fork from them,
+    /// and returns the number of commands that were removed.
+    #[cfg(feature = "chrono")]
+    pub fn prune_older_than(&mut self, time: &DateTime<impl TimeZone>) -> usize {
+        let pruned = self.record.prune_older_than(time);
+        if pruned > 0 {
+            self.rm_evicted(pruned);
+        }
+        pruned
     }
 
     pub(crate) fn jump_to(&mut self, root: usize) {
         let mut branch = self.branches.remove(&root).unwrap();
-        debug_assert_eq!(branch.parent, self.at());
+        debug_assert_eq!(branch.parent, self.position());
         let current = self.current();
         let saved = self.record.saved.filter(|&saved| saved > current);
+        let bookmarks = self.tail_bookmarks(current);
         let tail = self.record.entries.split_off(current);
         self.record.entries.append(&mut branch.entries);
-        self.branches
-            .insert(self.root, Branch::new(root, current, tail));
-        self.set_root(root, current, saved);
+        let old = self.branch();
+        self.branches.insert(old, Branch::new(root, current, tail));
+        self.set_root(root, current, saved, bookmarks);
+        if self.branches[&old].entries.is_empty() {
+            self.branches.remove(&old);
+        }
     }
 
-    fn set_root(&mut self, root: usize, current: usize, saved: Option<usize>) {
+    /// Returns the bookmarks after `current` in the root branch.
+    fn tail_bookmarks(&self, current: usize) -> BTreeMap<String, usize> {
+        self.record
+            .bookmarks
+            .iter()
+            .filter(|&(_, &bookmark)| bookmark > current)
+            .map(|(name, &bookmark)| (name.clone(), bookmark))
+            .collect()
+    }
+
+    fn set_root(
+        &mut self,
+        root: usize,
+        current: usize,
+        saved: Option<usize>,
+        bookmarks: BTreeMap<String, usize>,
+    ) {
         let old = self.branch();
         self.root = root;
         debug_assert_ne!(old, root);
+        self.record.emit(Signal::BranchChanged { old, new: root });
         // Handle the child branches.
         self.branches
             .values_mut()
             .filter(|branch| branch.parent.branch == old && branch.parent.current <= current)
             .for_each(|branch| branch.parent.branch = root);
-        match (self.record.saved, saved, self.saved) {
-            (Some(_), None, None) | (None, None, Some(_)) => self.swap_saved(root, old, current),
-            (None, Some(_), None) => {
-                self.record.saved = saved;
-                self.swap_saved(old, root, current);
-            }
-            (None, None, None) => (),
-            _ => unreachable!(),
-        }
-    }
-
-    fn swap_saved(&mut self, old: usize, new: usize, current: usize) {
-        debug_assert_ne!(old, new);
-        if let Some(At { current: saved, .. }) = self
-            .saved
-            .filter(|at| at.branch == new && at.current <= current)
-        {
-            self.saved = None;
-            self.record.saved = Some(saved);
-            self.record.slot.emit(Signal::Saved(true));
-        } else if let Some(saved) = self.record.saved {
+        // Handle the saved state, the positions after current now belong to the old branch.
+        if let Some(saved) = saved {
             self.saved = Some(At::new(old, saved));
             self.record.saved = None;
-            self.record.slot.emit(Signal::Saved(false));
+        } else if let Some(At { current: saved, .. }) = self.saved.filter(|at| at.branch == root) {
+            self.saved = None;
+            self.record.saved = Some(saved);
         }
+        // Handle the bookmarks the same way as the saved state.
+        let record = &mut self.record;
+        record
+            .bookmarks
+            .retain(|_, &mut bookmark| bookmark <= current);
+        self.bookmarks.retain(|name, at| {
+            if at.branch == root {
+                record.bookmarks.insert(name.clone(), at.current);
+            }
+            at.branch != root
+        });
+        self.bookmarks.extend(
+            bookmarks
+                .into_iter()
+                .map(|(name, bookmark)| (name, At::new(old, bookmark))),
+        );
     }
 
     fn rm_child(&mut self, branch: usize, current: usize) {
@@ -367,6 +820,7 @@ impl<C: Command, F: FnMut(Signal)> History<C, F> {
             // Remove the dead branch.
             self.branches.remove(&parent).unwrap();
             self.saved = self.saved.filter(|saved| saved.branch != parent);
+            self.bookmarks.retain(|_, at| at.branch != parent);
             // Add the children of the dead branch so they are removed too.
             dead.extend(
                 self.branches
@@ -377,16 +831,14 @@ impl<C: Command, F: FnMut(Signal)> History<C, F> {
         }
     }
 
-    fn mk_path(&mut self, mut to: usize) -> Option<impl Iterator<Item = (usize, Branch<C>)>> {
+    fn mk_path(&self, mut to: usize) -> Option<impl Iterator<Item = usize>> {
         debug_assert_ne!(self.branch(), to);
-        let mut dest = self.branches.remove(&to)?;
-        let mut i = dest.parent.branch;
-        let mut path = vec![(to, dest)];
-        while i != self.branch() {
-            dest = self.branches.remove(&i).unwrap();
-            to = i;
-            i = dest.parent.branch;
-            path.push((to, dest));
+        let mut dest = self.branches.get(&to)?;
+        let mut path = vec![to];
+        while dest.parent.branch != self.branch() {
+            to = dest.parent.branch;
+            dest = &self.branches[&to];
+            path.push(to);
         }
         Some(path.into_iter().rev())
     }
@@ -423,6 +875,7 @@ impl<C: Command, F> From<Record<C, F>> for History<C, F> {
             root: 0,
             next: 1,
             saved: None,
+            bookmarks: BTreeMap::new(),
             record,
             branches: BTreeMap::default(),
         }
@@ -439,6 +892,7 @@ where
             .field("root", &self.root)
             .field("next", &self.next)
             .field("saved", &self.saved)
+            .field("bookmarks", &self.bookmarks)
             .field("record", &self.record)
             .field("branches", &self.branches)
             .finish()
@@ -462,6 +916,61 @@ impl<C> Branch<C> {
     }
 }
 
+/// A read-only view of a branch that is not the current branch in the history.
+///
+/// The commands in a branch have not been applied to the target.
+/// Use [`go_to`](struct.History.html#method.go_to) to move to one of them.
+#[derive(Debug)]
+pub struct BranchRef<'a, C> {
+    id: usize,
+    branch: &'a Branch<C>,
+    saved: Option<usize>,
+}
+
+impl<'a, C> BranchRef<'a, C> {
+    /// Returns the id of the branch.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns the position the branch forks from.
+    pub fn parent(&self) -> At {
+        self.branch.parent
+    }
+
+    /// Returns the number of commands in the branch.
+    pub fn len(&self) -> usize {
+        self.branch.entries.len()
+    }
+
+    /// Returns `true` if the branch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.branch.entries.is_empty()
+    }
+
+    /// Returns an iterator over the commands in the branch, starting with the oldest.
+    ///
+    /// The index of a command is its position in the branch, which continues from the position
+    /// the branch forks from. The state after the command at index `i` is reached
+    /// by giving `i + 1` as the `current` argument to `go_to`.
+    pub fn entries(&self) -> Entries<'a, C> {
+        Entries::new(
+            self.branch.entries.iter(),
+            self.branch.parent.current,
+            0,
+            self.saved,
+        )
+    }
+}
+
+impl<C> Clone for BranchRef<'_, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for BranchRef<'_, C> {}
+
 /// Builder for a History.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug)]
@@ -492,6 +1001,23 @@ impl Builder {
         self
     }
 
+    /// Sets the `budget` for the history.
+    ///
+    /// When the total [size] of the commands in the current branch exceeds the budget,
+    /// the oldest commands are removed until it fits again,
+    /// together with the branches that fork from them.
+    /// The current command is always kept, even if it is larger than the budget.
+    /// By default there is no budget.
+    ///
+    /// Only the current branch is bounded by the budget. The commands in the other branches
+    /// are neither counted nor removed, so a history with branches can use more than the budget.
+    ///
+    /// [size]: ../trait.Command.html#method.size_hint
+    pub fn budget(&mut self, budget: usize) -> &mut Builder {
+        self.inner.budget(budget);
+        self
+    }
+
     /// Sets if the target is initially in a saved state.
     /// By default the target is in a saved state.
     pub fn saved(&mut self, saved: bool) -> &mut Builder {
@@ -499,12 +1025,42 @@ impl Builder {
         self
     }
 
+    /// Sets the interval in which a command must be applied after the previous command
+    /// for them to be merged.
+    ///
+    /// The time stamp of an entry is updated when a command is merged into it,
+    /// so the interval is measured from the last command that was merged.
+    /// By default there is no interval.
+    ///
+    /// Requires the `chrono` feature to be enabled.
+    #[cfg(feature = "chrono")]
+    pub fn merge_interval(&mut self, interval: Duration) -> &mut Builder {
+        self.inner.merge_interval(interval);
+        self
+    }
+
+    /// Sets how long the commands that can be undone in the current branch are kept.
+    ///
+    /// When a command is applied, the commands that are older than the retention are removed,
+    /// except the applied command itself, together with the branches that fork from them.
+    /// See [`prune_older_than`](struct.History.html#method.prune_older_than) for removing them manually.
+    /// By default the commands are kept regardless of their age.
+    ///
+    /// Requires the `chrono` feature to be enabled.
+    #[cfg(feature = "chrono")]
+    pub fn retention(&mut self, retention: Duration) -> &mut Builder {
+        self.inner.retention(retention);
+        self
+    }
+
     /// Builds the history.
     pub fn build<C: Command>(&self, target: C::Target) -> History<C> {
         History::from(self.inner.build(target))
     }
 
     /// Builds the history with the slot.
+    ///
+    /// Use [`connect`](struct.History.html#method.connect) instead if the slot needs to be disconnected later.
     pub fn build_with<C: Command, F>(&self, target: C::Target, slot: F) -> History<C, F> {
         History::from(self.inner.build_with(target, slot))
     }
@@ -564,7 +1120,7 @@ enum QueueCommand<C> {
 /// queue.apply(Add('b'));
 /// queue.apply(Add('c'));
 /// assert_eq!(queue.target(), "");
-/// queue.commit()?;
+/// queue.commit().map_err(|err| err.error)?;
 /// assert_eq!(record.target(), "abc");
 /// # Ok(())
 /// # }
@@ -574,7 +1130,7 @@ pub struct Queue<'a, C: Command, F> {
     commands: Vec<QueueCommand<C>>,
 }
 
-impl<C: Command, F: FnMut(Signal)> Queue<'_, C, F> {
+impl<C: Command, F: Observer> Queue<'_, C, F> {
     /// Queues an `apply` action.
     pub fn apply(&mut self, command: C) {
         self.commands.push(QueueCommand::Apply(command));
@@ -592,29 +1148,42 @@ impl<C: Command, F: FnMut(Signal)> Queue<'_, C, F> {
 
     /// Applies the queued commands.
     ///
+    /// The queued commands are not merged with other commands.
+    ///
     /// # Errors
-    /// If an error occurs, it stops applying the commands and returns the error.
-    pub fn commit(self) -> Result<C> {
-        for command in self.commands {
-            match command {
-                QueueCommand::Apply(command) => self.history.apply(command)?,
-                QueueCommand::Undo => self.history.undo()?,
-                QueueCommand::Redo => self.history.redo()?,
+    /// If an error occurs, it stops applying the commands, cancels the changes made by the commit,
+    /// and returns the error together with the index of the action that failed.
+    /// Errors that occur while canceling the changes are ignored.
+    pub fn commit(self) -> core::result::Result<(), QueueError<C::Error>> {
+        let commands = self.commands;
+        self.history.batch(|history| {
+            let mut checkpoint = history.checkpoint();
+            for (index, command) in commands.into_iter().enumerate() {
+                let result = match command {
+                    QueueCommand::Apply(command) => checkpoint.apply(command),
+                    QueueCommand::Undo => checkpoint.undo(),
+                    QueueCommand::Redo => checkpoint.redo(),
+                };
+                if let Err(error) = result {
+                    let _ = checkpoint.cancel();
+                    return Err(QueueError { index, error });
+                }
             }
-        }
-        Ok(())
+            checkpoint.commit();
+            Ok(())
+        })
     }
 
     /// Cancels the queued actions.
     pub fn cancel(self) {}
 
     /// Returns a queue.
-    pub fn queue(&mut self) -> Queue<C, F> {
+    pub fn queue(&mut self) -> Queue<'_, C, F> {
         self.history.queue()
     }
 
     /// Returns a checkpoint.
-    pub fn checkpoint(&mut self) -> Checkpoint<C, F> {
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, C, F> {
         self.history.checkpoint()
     }
 
@@ -641,16 +1210,50 @@ enum CheckpointCommand {
 }
 
 /// Wraps a history and gives it checkpoint functionality.
-pub struct Checkpoint<'a, C: Command, F> {
+///
+/// If the checkpoint is dropped without calling [`commit`] the changes are canceled,
+/// unless it was created using [`commit_on_drop`].
+/// Commands applied through the checkpoint are not merged with other commands,
+/// use [`commit_squashed`] to merge them when committing.
+/// No commands are removed from the history because of its limit, retention, or budget
+/// while the checkpoint is open, so the changes can always be canceled.
+/// The commands are instead removed when the checkpoint is committed.
+///
+/// [`commit`]: struct.Checkpoint.html#method.commit
+/// [`commit_on_drop`]: struct.Checkpoint.html#method.commit_on_drop
+/// [`commit_squashed`]: struct.Checkpoint.html#method.commit_squashed
+pub struct Checkpoint<'a, C: Command, F: Observer> {
     history: &'a mut History<C, F>,
     commands: Vec<CheckpointCommand>,
+    low: usize,
+    label: Option<String>,
+    cancel_on_drop: bool,
+    on_drop_error: Option<Box<dyn FnOnce(C::Error) + 'a>>,
 }
 
-impl<C: Command, F: FnMut(Signal)> Checkpoint<'_, C, F> {
+impl<'a, C: Command, F: Observer> Checkpoint<'a, C, F> {
+    /// Returns a checkpoint that keeps the changes if it is dropped without being committed or canceled.
+    pub fn commit_on_drop(history: &'a mut History<C, F>) -> Checkpoint<'a, C, F> {
+        let mut checkpoint = Checkpoint::from(history);
+        checkpoint.cancel_on_drop = false;
+        checkpoint
+    }
+
+    /// Sets the function that is called with the error if canceling the changes fails
+    /// when the checkpoint is dropped.
+    pub fn on_drop_error(&mut self, f: impl FnOnce(C::Error) + 'a) {
+        self.on_drop_error = Some(Box::new(f));
+    }
+}
+
+impl<C: Command, F: Observer> Checkpoint<'_, C, F> {
     /// Calls the `apply` method.
     pub fn apply(&mut self, command: C) -> Result<C> {
         let branch = self.history.branch();
-        self.history.apply(command)?;
+        let current = self.history.current();
+        // Commands are not merged so each apply can be rolled back by removing its own entry.
+        self.history.__apply(command, false)?;
+        self.low = self.low.min(current);
         self.commands.push(CheckpointCommand::Apply(branch));
         Ok(())
     }
@@ -674,39 +1277,95 @@ impl<C: Command, F: FnMut(Signal)> Checkpoint<'_, C, F> {
     }
 
     /// Commits the changes and consumes the checkpoint.
-    pub fn commit(self) {}
+    ///
+    /// If the checkpoint has a label and the current command was applied through the checkpoint,
+    /// the label is given to the command and the command is sealed.
+    pub fn commit(mut self) {
+        self.commands.clear();
+        if let Some(label) = self.label.take() {
+            if self.history.current() > self.low {
+                self.history.record.set_label(label);
+            }
+        }
+    }
+
+    /// Commits the changes as a single entry in the history and consumes the checkpoint.
+    ///
+    /// The applied commands are merged using their [`merge`] method if possible,
+    /// otherwise they are stored in a [`Group`].
+    /// Branches created inside the checkpoint that fork between the applied commands are removed.
+    /// If the checkpoint has a label, the label is given to the entry and the entry is sealed.
+    ///
+    /// [`merge`]: ../trait.Command.html#method.merge
+    /// [`Group`]: ../struct.Group.html
+    pub fn commit_squashed(mut self)
+    where
+        C: From<Group<C>>,
+    {
+        self.commands.clear();
+        let label = self.label.take();
+        self.history.squash(self.low, label);
+    }
+
+    /// Sets the label that is given to the entry created by [`commit_squashed`],
+    /// or to the last command applied through the checkpoint by [`commit`].
+    ///
+    /// The label is shown instead of the text of the command
+    /// by [`undo_text`], [`redo_text`], and the display structure.
+    ///
+    /// [`commit`]: struct.Checkpoint.html#method.commit
+    /// [`commit_squashed`]: struct.Checkpoint.html#method.commit_squashed
+    /// [`undo_text`]: struct.History.html#method.undo_text
+    /// [`redo_text`]: struct.History.html#method.redo_text
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
+    }
 
     /// Cancels the changes and consumes the checkpoint.
     ///
     /// # Errors
     /// If an error occur when canceling the changes, the error is returned
     /// and the remaining commands are not canceled.
-    pub fn cancel(self) -> Result<C> {
-        for command in self.commands.into_iter().rev() {
-            match command {
-                CheckpointCommand::Apply(branch) => {
-                    let root = self.history.branch();
-                    self.history.jump_to(branch);
-                    if root == branch {
-                        self.history.record.entries.pop_back();
-                    } else {
-                        self.history.branches.remove(&root).unwrap();
+    pub fn cancel(mut self) -> Result<C> {
+        self.rollback()
+    }
+
+    fn rollback(&mut self) -> Result<C> {
+        let commands = mem::take(&mut self.commands);
+        self.history.batch(|history| {
+            for command in commands.into_iter().rev() {
+                match command {
+                    CheckpointCommand::Apply(branch) => {
+                        let root = history.branch();
+                        history.record.undo()?;
+                        history.record.entries.pop_back();
+                        let current = history.current();
+                        history.record.saved =
+                            history.record.saved.filter(|&saved| saved <= current);
+                        history
+                            .record
+                            .bookmarks
+                            .retain(|_, &mut bookmark| bookmark <= current);
+                        // Move the commands that were replaced by the apply back into the root branch.
+                        if root != branch {
+                            history.jump_to(branch);
+                        }
                     }
+                    CheckpointCommand::Undo => history.redo()?,
+                    CheckpointCommand::Redo => history.undo()?,
                 }
-                CheckpointCommand::Undo => self.history.redo()?,
-                CheckpointCommand::Redo => self.history.undo()?,
             }
-        }
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Returns a queue.
-    pub fn queue(&mut self) -> Queue<C, F> {
+    pub fn queue(&mut self) -> Queue<'_, C, F> {
         self.history.queue()
     }
 
     /// Returns a checkpoint.
-    pub fn checkpoint(&mut self) -> Checkpoint<C, F> {
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, C, F> {
         self.history.checkpoint()
     }
 
@@ -716,11 +1375,32 @@ impl<C: Command, F: FnMut(Signal)> Checkpoint<'_, C, F> {
     }
 }
 
-impl<'a, C: Command, F> From<&'a mut History<C, F>> for Checkpoint<'a, C, F> {
+impl<C: Command, F: Observer> Drop for Checkpoint<'_, C, F> {
+    fn drop(&mut self) {
+        if self.cancel_on_drop {
+            if let Err(err) = self.rollback() {
+                if let Some(f) = self.on_drop_error.take() {
+                    f(err);
+                }
+            }
+        }
+        let evicted = self.history.record.end_checkpoint();
+        if evicted > 0 {
+            self.history.rm_evicted(evicted);
+        }
+    }
+}
+
+impl<'a, C: Command, F: Observer> From<&'a mut History<C, F>> for Checkpoint<'a, C, F> {
     fn from(history: &'a mut History<C, F>) -> Self {
+        history.record.begin_checkpoint();
         Checkpoint {
+            low: history.current(),
             history,
             commands: Vec::new(),
+            label: None,
+            cancel_on_drop: true,
+            on_drop_error: None,
         }
     }
 }
@@ -865,7 +1545,11 @@ impl<C: Command + fmt::Display, F> fmt::Display for Display<'_, C, F> {
 #[cfg(test)]
 mod tests {
     use crate::*;
-    use alloc::string::String;
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::cell::RefCell;
 
     struct Add(char);
 
@@ -884,6 +1568,57 @@ mod tests {
         }
     }
 
+    struct Sum(i32);
+
+    impl Command for Sum {
+        type Target = i32;
+        type Error = &'static str;
+
+        fn apply(&mut self, n: &mut i32) -> Result<Sum> {
+            if self.0 == 0 {
+                return Err("zero");
+            }
+            *n += self.0;
+            Ok(())
+        }
+
+        fn undo(&mut self, n: &mut i32) -> Result<Sum> {
+            *n -= self.0;
+            Ok(())
+        }
+
+        fn merge(&mut self, Sum(n): Self) -> Merge<Self> {
+            if self.0 + n == 0 {
+                Merge::Annul
+            } else {
+                self.0 += n;
+                Merge::Yes
+            }
+        }
+    }
+
+    struct Push(String);
+
+    impl Command for Push {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Push> {
+            s.push_str(&self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Push> {
+            s.truncate(s.len() - self.0.len());
+            Ok(())
+        }
+
+        fn merge(&mut self, Push(s): Self) -> Merge<Self> {
+            self.0.push_str(&s);
+            Merge::Yes
+        }
+    }
+
     #[test]
     fn go_to() {
         //          m
@@ -958,4 +1693,490 @@ mod tests {
         history.go_to(abnpq, 5).unwrap().unwrap();
         assert_eq!(history.target(), "abnpq");
     }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn time_travel() {
+        use chrono::{Duration, Utc};
+
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.apply(Add('c')).unwrap();
+        history.go_to(0, 1).unwrap().unwrap();
+        history.apply(Add('d')).unwrap();
+        history.apply(Add('e')).unwrap();
+        // Give each command a time stamp in the order they were applied.
+        let start = Utc::now();
+        let entries = history
+            .record
+            .entries
+            .iter_mut()
+            .chain(history.branches.values_mut().flat_map(|b| &mut b.entries));
+        for entry in entries {
+            entry.timestamp = start + Duration::minutes(entry.seq as i64);
+        }
+        history
+            .time_travel(&(start + Duration::seconds(150)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.target(), "ab");
+        history
+            .time_travel(&(start + Duration::seconds(270)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.target(), "ad");
+        history.time_travel(&start).unwrap().unwrap();
+        assert_eq!(history.target(), "");
+        history
+            .time_travel(&(start + Duration::seconds(210)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.target(), "abc");
+        assert_eq!(history.travel_forward(Duration::minutes(1)), Ok(3));
+        assert_eq!(history.target(), "ad");
+        assert_eq!(history.travel_back(Duration::seconds(30)), Ok(3));
+        assert_eq!(history.target(), "abc");
+        // An exact match goes to the state before the command, the same as in a record.
+        history
+            .time_travel(&(start + Duration::minutes(4)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.target(), "abc");
+        history
+            .time_travel(&(start + Duration::minutes(2)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.target(), "a");
+        // Commands made at the same time are ordered by when they were applied.
+        let e = history.branches.values_mut().next().unwrap();
+        e.entries[1].timestamp = start + Duration::minutes(3);
+        history
+            .time_travel(&(start + Duration::seconds(210)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.target(), "ade");
+    }
+
+    #[test]
+    fn budget() {
+        let mut history = history::Builder::new().budget(3).default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        let ab = history.branches.keys().copied().next().unwrap();
+        history.apply(Add('d')).unwrap();
+        history.apply(Add('e')).unwrap();
+        assert_eq!(history.len(), 3);
+        // The branch forks after the evicted command, so it is kept.
+        assert_eq!(history.branches[&ab].parent.current, 0);
+        history.go_to(ab, 1).unwrap().unwrap();
+        assert_eq!(history.target(), "ab");
+        let acde = history.branches.keys().copied().next().unwrap();
+        history.go_to(acde, 3).unwrap().unwrap();
+        assert_eq!(history.target(), "acde");
+        // The branch forks before the evicted command, so it is removed.
+        history.apply(Add('f')).unwrap();
+        assert!(history.branches.is_empty());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn prune_older_than() {
+        use chrono::{Duration, Utc};
+
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        history.apply(Add('d')).unwrap();
+        let ab = history.branches.keys().copied().next().unwrap();
+        history.record.entries[0].timestamp -= Duration::minutes(20);
+        assert_eq!(
+            history.prune_older_than(&(Utc::now() - Duration::minutes(10))),
+            1
+        );
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.branches[&ab].parent.current, 0);
+        history.go_to(ab, 1).unwrap().unwrap();
+        assert_eq!(history.target(), "ab");
+        // The branch with 'c' and 'd' forks before the pruned command, so it is removed.
+        assert_eq!(history.prune_older_than(&Utc::now()), 1);
+        assert!(history.branches.is_empty());
+        assert!(history.is_empty());
+        assert_eq!(history.target(), "ab");
+    }
+
+    #[test]
+    fn branches() {
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.apply(Add('c')).unwrap();
+        let abc = history.branch();
+        history.go_to(abc, 1).unwrap().unwrap();
+        history.apply(Add('d')).unwrap();
+        history.go_to(abc, 1).unwrap().unwrap();
+        history.apply(Add('e')).unwrap();
+        let ae = history.branch();
+        assert_eq!(history.branches().count(), 2);
+        let branch = history.get_branch(abc).unwrap();
+        assert_eq!(branch.parent(), At::new(ae, 1));
+        assert_eq!(branch.len(), 2);
+        let entries: Vec<_> = branch
+            .entries()
+            .map(|entry| (entry.index(), entry.command().0))
+            .collect();
+        assert_eq!(entries, [(1, 'b'), (2, 'c')]);
+        assert!(branch.entries().all(|entry| !entry.is_applied()));
+        assert!(history.get_branch(ae).is_none());
+        let children: Vec<_> = history.children(At::new(ae, 1)).collect();
+        assert_eq!(children.len(), 2);
+        assert!(children.contains(&abc));
+        assert_eq!(history.children(At::new(ae, 0)).count(), 0);
+        let ad = children.into_iter().find(|&id| id != abc).unwrap();
+        history.go_to(ad, 2).unwrap().unwrap();
+        assert_eq!(history.target(), "ad");
+    }
+
+    #[test]
+    fn position() {
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.set_saved(true);
+        let ab = history.position();
+        assert_eq!(history.saved(), Some(ab));
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        assert_ne!(history.branch(), ab.branch);
+        assert_eq!(history.saved(), Some(ab));
+        history.go_to_at(ab).unwrap().unwrap();
+        assert_eq!(history.target(), "ab");
+        assert!(history.is_saved());
+        assert_eq!(history.position(), ab);
+        assert_eq!(ab.to_string(), "0:2");
+        assert!(At::new(0, 2) < At::new(1, 0));
+    }
+
+    #[test]
+    fn earlier_later() {
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        history.undo().unwrap();
+        history.apply(Add('d')).unwrap();
+        history.earlier(1).unwrap();
+        assert_eq!(history.target(), "ac");
+        history.earlier(1).unwrap();
+        assert_eq!(history.target(), "ab");
+        history.earlier(1).unwrap();
+        assert_eq!(history.target(), "a");
+        history.earlier(5).unwrap();
+        assert_eq!(history.target(), "");
+        history.later(2).unwrap();
+        assert_eq!(history.target(), "ab");
+        history.later(10).unwrap();
+        assert_eq!(history.target(), "ad");
+        // Applying a command creates a new state that is later than all the others.
+        history.earlier(2).unwrap();
+        history.apply(Add('e')).unwrap();
+        history.earlier(1).unwrap();
+        assert_eq!(history.target(), "ad");
+    }
+
+    #[test]
+    fn bookmarks() {
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.set_bookmark("ab");
+        let ab = history.position();
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        history.set_bookmark("ac");
+        let ac = history.position();
+        assert_eq!(history.bookmark("ab"), Some(ab));
+        assert_eq!(history.bookmark("ac"), Some(ac));
+        history.go_to_at(ab).unwrap().unwrap();
+        assert!(history.is_at_bookmark("ab"));
+        assert_eq!(history.bookmark("ac"), Some(ac));
+        // Moving a bookmark from another branch.
+        history.set_bookmark("ac");
+        assert!(history.is_at_bookmark("ac"));
+        assert_eq!(history.bookmarks().count(), 2);
+        history.go_to_bookmark("ab").unwrap().unwrap();
+        assert_eq!(history.target(), "ab");
+        assert_eq!(history.remove_bookmark("ab"), Some(ab));
+        assert_eq!(history.bookmark("ab"), None);
+        assert!(history.go_to_bookmark("ab").is_none());
+    }
+
+    #[test]
+    fn revert() {
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.set_saved(true);
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        history.apply(Add('d')).unwrap();
+        assert!(!history.is_saved());
+        history.revert().unwrap().unwrap();
+        assert!(history.is_saved());
+        assert_eq!(history.target(), "ab");
+        history.set_saved(false);
+        assert!(history.revert().is_none());
+    }
+
+    #[test]
+    fn transaction_commit() {
+        let mut history = History::default();
+        let mut transaction = history.transaction("ab");
+        transaction.apply(Push("a".into())).unwrap();
+        transaction.apply(Push("b".into())).unwrap();
+        transaction.commit();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(1).unwrap().label(), Some("ab"));
+        // The labeled entry is sealed.
+        history.apply(Push("c".into())).unwrap();
+        assert_eq!(history.len(), 3);
+        // Nothing is labeled if no commands were applied.
+        let mut transaction = history.transaction("undo");
+        transaction.undo().unwrap();
+        transaction.commit();
+        assert_eq!(history.get(2).unwrap().label(), None);
+    }
+
+    #[test]
+    fn checkpoint_limit() {
+        let mut history = history::Builder::new().limit(2).default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.set_saved(true);
+        history.set_bookmark("x");
+        let mut cp = history.checkpoint();
+        cp.apply(Add('c')).unwrap();
+        assert_eq!(cp.target(), "abc");
+        cp.cancel().unwrap();
+        assert_eq!(history.target(), "ab");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.current(), 2);
+        assert!(history.is_saved());
+        assert!(history.is_at_bookmark("x"));
+        // The commands are evicted when the checkpoint is committed.
+        let mut cp = history.checkpoint();
+        cp.apply(Add('c')).unwrap();
+        cp.apply(Add('d')).unwrap();
+        cp.commit();
+        assert_eq!(history.target(), "abcd");
+        assert_eq!(history.len(), 2);
+        history.undo().unwrap();
+        history.undo().unwrap();
+        assert_eq!(history.target(), "ab");
+        assert!(history.is_saved());
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn checkpoint_drop_merge() {
+        let mut history = History::default();
+        history.apply(Sum(5)).unwrap();
+        history.seal();
+        history.apply(Sum(1)).unwrap();
+        let mut cp = history.checkpoint();
+        cp.apply(Sum(-1)).unwrap();
+        assert_eq!(*cp.target(), 5);
+        drop(cp);
+        assert_eq!(*history.target(), 6);
+        assert_eq!(history.len(), 2);
+        let mut cp = history.checkpoint();
+        cp.apply(Sum(2)).unwrap();
+        cp.apply(Sum(3)).unwrap();
+        drop(cp);
+        assert_eq!(*history.target(), 6);
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn queue_rollback_limit() {
+        let mut history = history::Builder::new().limit(2).default();
+        history.apply(Sum(1)).unwrap();
+        history.seal();
+        history.apply(Sum(2)).unwrap();
+        history.set_saved(true);
+        history.set_bookmark("x");
+        let mut queue = history.queue();
+        queue.apply(Sum(3));
+        queue.apply(Sum(4));
+        queue.apply(Sum(0));
+        assert_eq!(queue.commit().unwrap_err().index, 2);
+        assert_eq!(*history.target(), 3);
+        assert_eq!(history.len(), 2);
+        assert!(history.is_saved());
+        assert!(history.is_at_bookmark("x"));
+        history.undo().unwrap();
+        history.undo().unwrap();
+        assert_eq!(*history.target(), 0);
+    }
+
+    #[test]
+    fn checkpoint_drop_limit() {
+        let mut history = history::Builder::new().limit(1).default();
+        history.apply(Sum(1)).unwrap();
+        history.set_saved(true);
+        {
+            let mut cp = history.checkpoint();
+            cp.apply(Sum(2)).unwrap();
+            cp.apply(Sum(3)).unwrap();
+            assert_eq!(*cp.target(), 6);
+        }
+        assert_eq!(*history.target(), 1);
+        assert_eq!(history.len(), 1);
+        assert!(history.is_saved());
+        {
+            let mut cp = history::Checkpoint::commit_on_drop(&mut history);
+            cp.apply(Sum(2)).unwrap();
+            cp.apply(Sum(3)).unwrap();
+        }
+        assert_eq!(*history.target(), 6);
+        assert_eq!(history.len(), 1);
+        history.undo().unwrap();
+        assert_eq!(*history.target(), 3);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn queue_rollback_merge() {
+        let mut history = History::default();
+        history.apply(Sum(5)).unwrap();
+        let mut queue = history.queue();
+        queue.apply(Sum(1));
+        queue.apply(Sum(0));
+        assert_eq!(queue.commit().unwrap_err().index, 1);
+        assert_eq!(*history.target(), 5);
+        assert_eq!(history.len(), 1);
+        // The queued command would annul the current command if it was merged.
+        let mut queue = history.queue();
+        queue.apply(Sum(-5));
+        queue.apply(Sum(0));
+        assert_eq!(queue.commit().unwrap_err().index, 1);
+        assert_eq!(*history.target(), 5);
+        assert_eq!(history.len(), 1);
+        // Merging still works outside of queues and checkpoints.
+        history.apply(Sum(-5)).unwrap();
+        assert_eq!(*history.target(), 0);
+        assert_eq!(history.len(), 0);
+    }
+
+    #[test]
+    fn checkpoint_cancel() {
+        let mut history = History::default();
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.apply(Add('c')).unwrap();
+        history.set_saved(true);
+        history.undo().unwrap();
+        history.undo().unwrap();
+        let abc = history.branch();
+        let mut cp = history.checkpoint();
+        cp.apply(Add('d')).unwrap();
+        cp.apply(Add('e')).unwrap();
+        cp.undo().unwrap();
+        cp.apply(Add('f')).unwrap();
+        assert_eq!(cp.target(), "adf");
+        cp.cancel().unwrap();
+        assert_eq!(history.target(), "a");
+        assert_eq!(history.branch(), abc);
+        assert!(history.branches.is_empty());
+        history.go_to(abc, 3).unwrap().unwrap();
+        assert_eq!(history.target(), "abc");
+        assert!(history.is_saved());
+        {
+            let mut cp = history.checkpoint();
+            cp.apply(Add('d')).unwrap();
+        }
+        assert_eq!(history.target(), "abc");
+        assert_eq!(history.len(), 3);
+    }
+
+    #[test]
+    fn checkpoint_squash() {
+        let mut history = History::default();
+        history.apply(BoxedCommand::new(Add('a'))).unwrap();
+        let mut cp = history.checkpoint();
+        cp.apply(BoxedCommand::new(Add('b'))).unwrap();
+        cp.apply(BoxedCommand::new(Add('c'))).unwrap();
+        cp.undo().unwrap();
+        cp.apply(BoxedCommand::new(Add('d'))).unwrap();
+        cp.apply(BoxedCommand::new(Add('e'))).unwrap();
+        cp.undo().unwrap();
+        let e = cp.history.branch();
+        cp.apply(BoxedCommand::new(Add('f'))).unwrap();
+        cp.undo().unwrap();
+        assert_eq!(cp.target(), "abd");
+        cp.commit_squashed();
+        // The branch with 'c' forked between the squashed commands.
+        assert_eq!(history.branches.len(), 1);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.current(), 2);
+        history.undo().unwrap();
+        assert_eq!(history.target(), "a");
+        history.redo().unwrap();
+        assert_eq!(history.target(), "abd");
+        history.redo().unwrap();
+        assert_eq!(history.target(), "abdf");
+        history.go_to(e, 3).unwrap().unwrap();
+        assert_eq!(history.target(), "abde");
+    }
+
+    #[test]
+    fn branch_changed() {
+        let signals = RefCell::new(Vec::new());
+        let mut history = history::Builder::new().build_with(String::new(), |signal| {
+            if let Signal::BranchChanged { .. } = signal {
+                signals.borrow_mut().push(signal);
+            }
+        });
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        let ab = history.branch();
+        history.undo().unwrap();
+        history.apply(Add('c')).unwrap();
+        let ac = history.branch();
+        history.go_to(ab, 2).unwrap().unwrap();
+        assert_eq!(
+            signals.into_inner(),
+            [
+                Signal::BranchChanged { old: ab, new: ac },
+                Signal::BranchChanged { old: ac, new: ab },
+            ]
+        );
+    }
+
+    #[test]
+    fn seal() {
+        let mut history = History::default();
+        history.set_saved(false);
+        history.apply(Push("a".into())).unwrap();
+        history.seal();
+        history.apply(Push("b".into())).unwrap();
+        history.seal();
+        history.apply(Push("c".into())).unwrap();
+        let abc = history.branch();
+        assert_eq!(history.len(), 3);
+        history.go_to(abc, 1).unwrap().unwrap();
+        history.apply(Push("d".into())).unwrap();
+        assert_eq!(history.len(), 2);
+        // Going back to the branch must not merge the sealed commands.
+        history.go_to(abc, 3).unwrap().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.target(), "abc");
+        history.undo().unwrap();
+        assert_eq!(history.target(), "ab");
+    }
 }
@@ -10,6 +10,9 @@
 //! * [Command](trait.Command.html) provides the base functionality for all commands.
 //! * [Record](struct.Record.html) provides basic linear undo-redo functionality.
 //! * [History](struct.History.html) provides non-linear undo-redo functionality that allows you to jump between different branches.
+//! * [BoxedCommand](struct.BoxedCommand.html) allows commands of different types to be stored in the same record or history.
+//! * [FromFn](struct.FromFn.html) allows commands to be made from closures.
+//! * [Group](struct.Group.html) applies a sequence of commands as a single command.
 //! * Queue wraps a record or history and extends them with queue functionality.
 //! * Checkpoint wraps a record or history and extends them with checkpoint functionality.
 //! * Commands can be merged into a single command by implementing the
@@ -19,6 +22,7 @@
 //! * The target can be marked as being saved to disk and the data-structures can track the saved state and notify
 //!   when it changes.
 //! * The amount of changes being tracked can be configured by the user so only the `N` most recent changes are stored.
+//!   A budget can also be set on the total [size](trait.Command.html#method.size_hint) of the commands.
 //! * Configurable display formatting using the display structure.
 //! * The library can be used as `no_std` by default.
 //!
@@ -26,6 +30,7 @@
 //!
 //! * `chrono`: Enables time stamps and time travel.
 //! * `serde`: Enables serialization and deserialization.
+//! * `std`: Enables sending the signals to channels, and records and histories that can be shared between threads.
 
 #![no_std]
 #![doc(html_root_url = "https://docs.rs/redo")]
@@ -33,18 +38,46 @@
 #![forbid(unsafe_code)]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+mod boxed;
+mod entries;
 mod format;
+mod from_fn;
+mod group;
 pub mod history;
 pub mod record;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod sink;
 
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 #[cfg(feature = "chrono")]
 use chrono::{DateTime, Utc};
 use core::fmt;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-pub use self::{history::History, record::Record};
+pub use self::{
+    boxed::{BoxedCommand, DynCommand},
+    entries::{Entries, EntryRef},
+    from_fn::{command_fn, FromFn},
+    group::Group,
+    history::History,
+    record::Record,
+};
+#[cfg(feature = "std")]
+pub use self::{
+    shared::{Pending, SharedHistory, SharedRecord, TargetGuard},
+    sink::{channel, Forward, Sink},
+};
 
 /// A specialized Result type for undo-redo operations.
 pub type Result<C> = core::result::Result<(), <C as Command>::Error>;
@@ -75,17 +108,31 @@ pub trait Command: Sized {
     }
 
     /// Used for manual merging of commands.
+    ///
+    /// Commands are not merged into a sealed command, or a command at the saved state or a bookmark.
     fn merge(&mut self, command: Self) -> Merge<Self> {
         Merge::No(command)
     }
+
+    /// Returns the approximate size of the command.
+    ///
+    /// The size is used to keep the total size of the commands in a record or history
+    /// within the budget set by the builder. The unit is up to the user, e.g. bytes.
+    ///
+    /// The default implementation returns `1`.
+    fn size_hint(&self) -> usize {
+        1
+    }
 }
 
 /// The signal used for communicating state changes.
 ///
 /// For example, if the record can no longer redo any commands, it sends a `Redo(false)`
 /// signal to tell the user.
+///
+/// The signal is `Clone` but not `Copy`, since the `Bookmark` signal holds the name of the bookmark.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub enum Signal {
     /// Says if the structures can undo.
     Undo(bool),
@@ -93,6 +140,163 @@ pub enum Signal {
     Redo(bool),
     /// Says if the target is in a saved state.
     Saved(bool),
+    /// Says that a command was applied and pushed at the index.
+    Applied(usize),
+    /// Says that the command at the index was undone.
+    Undone(usize),
+    /// Says that the command at the index was redone.
+    Redone(usize),
+    /// Says that a command was merged into the command at the index.
+    Merged(usize),
+    /// Says that a command annulled the command at the index, which was removed.
+    Annulled(usize),
+    /// Says how many of the oldest commands were removed because the limit or budget was exceeded.
+    Evicted(usize),
+    /// Says if the target is at the bookmark with the name.
+    Bookmark(Arc<str>, bool),
+    /// Says that the current branch in a history changed from `old` to `new`.
+    BranchChanged {
+        /// The id of the previous branch.
+        old: usize,
+        /// The id of the new branch.
+        new: usize,
+    },
+}
+
+/// A signal together with the state of the record or history when it was emitted.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Message {
+    /// The signal.
+    pub signal: Signal,
+    /// The current position in the record or history.
+    pub current: usize,
+    /// The number of commands in the record or the current branch of the history.
+    pub len: usize,
+}
+
+/// Receives the signals emitted when the state of a record or history changes.
+///
+/// All methods have default implementations that do nothing, so only the events of interest
+/// need to be implemented. The [`receive`] method is called for every signal and passes it to
+/// [`notify`], which dispatches it to the other methods. The trait is implemented for all `FnMut(Signal)` closures,
+/// and for `Box<dyn Observer>`, so observers of different types can be connected to the same record or history.
+///
+/// # Examples
+/// ```
+/// # use redo::{Command, Observer, record::Builder};
+/// # struct Add(char);
+/// # impl Command for Add {
+/// #     type Target = String;
+/// #     type Error = &'static str;
+/// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         s.push(self.0);
+/// #         Ok(())
+/// #     }
+/// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         self.0 = s.pop().ok_or("s is empty")?;
+/// #         Ok(())
+/// #     }
+/// # }
+/// #[derive(Default)]
+/// struct Title {
+///     modified: bool,
+/// }
+///
+/// impl Observer for Title {
+///     fn on_saved_changed(&mut self, saved: bool) {
+///         self.modified = !saved;
+///     }
+/// }
+///
+/// # fn main() -> Result<(), &'static str> {
+/// let mut record = Builder::new().build_with(String::new(), Title::default());
+/// record.apply(Add('a'))?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`receive`]: trait.Observer.html#method.receive
+/// [`notify`]: trait.Observer.html#method.notify
+pub trait Observer {
+    /// Called with every signal and the state of the record or history when it was emitted.
+    ///
+    /// The default implementation calls [`notify`] with the signal.
+    ///
+    /// [`notify`]: trait.Observer.html#method.notify
+    fn receive(&mut self, message: Message) {
+        self.notify(message.signal)
+    }
+
+    /// Called with every signal.
+    ///
+    /// The default implementation calls the method that handles the signal.
+    fn notify(&mut self, signal: Signal) {
+        match signal {
+            Signal::Undo(can_undo) => self.on_undo_available(can_undo),
+            Signal::Redo(can_redo) => self.on_redo_available(can_redo),
+            Signal::Saved(saved) => self.on_saved_changed(saved),
+            Signal::Applied(index) => self.on_apply(index),
+            Signal::Undone(index) => self.on_undo(index),
+            Signal::Redone(index) => self.on_redo(index),
+            Signal::Merged(index) => self.on_merge(index),
+            Signal::Annulled(index) => self.on_annul(index),
+            Signal::Evicted(n) => self.on_evict(n),
+            Signal::Bookmark(name, at) => self.on_bookmark(&name, at),
+            Signal::BranchChanged { old, new } => self.on_branch_changed(old, new),
+        }
+    }
+
+    /// Called when it changes if the structures can undo.
+    fn on_undo_available(&mut self, _can_undo: bool) {}
+
+    /// Called when it changes if the structures can redo.
+    fn on_redo_available(&mut self, _can_redo: bool) {}
+
+    /// Called when it changes if the target is in a saved state.
+    fn on_saved_changed(&mut self, _saved: bool) {}
+
+    /// Called when a command is applied and pushed at the index.
+    fn on_apply(&mut self, _index: usize) {}
+
+    /// Called when the command at the index is undone.
+    fn on_undo(&mut self, _index: usize) {}
+
+    /// Called when the command at the index is redone.
+    fn on_redo(&mut self, _index: usize) {}
+
+    /// Called when a command is merged into the command at the index.
+    fn on_merge(&mut self, _index: usize) {}
+
+    /// Called when a command annuls the command at the index.
+    fn on_annul(&mut self, _index: usize) {}
+
+    /// Called when the `n` oldest commands are removed.
+    fn on_evict(&mut self, _n: usize) {}
+
+    /// Called when the target arrives at or leaves the bookmark with the name.
+    fn on_bookmark(&mut self, _name: &str, _at: bool) {}
+
+    /// Called when the current branch in a history changes.
+    fn on_branch_changed(&mut self, _old: usize, _new: usize) {}
+}
+
+impl<F: FnMut(Signal)> Observer for F {
+    fn notify(&mut self, signal: Signal) {
+        self(signal)
+    }
+}
+
+impl Observer for Box<dyn Observer> {
+    fn receive(&mut self, message: Message) {
+        (**self).receive(message)
+    }
+}
+
+impl Observer for Box<dyn Observer + Send> {
+    fn receive(&mut self, message: Message) {
+        (**self).receive(message)
+    }
 }
 
 /// Says if the command have been merged with another command.
@@ -107,52 +311,108 @@ pub enum Merge<C> {
     Annul,
 }
 
+/// The error returned when committing a queue fails.
+///
+/// When this error is returned, the changes made by the commit have been rolled back.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct QueueError<E> {
+    /// The index of the queued action that failed.
+    pub index: usize,
+    /// The error returned by the command.
+    pub error: E,
+}
+
+impl<E: fmt::Display> fmt::Display for QueueError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "queued action {} failed: {}", self.index, self.error)
+    }
+}
+
 /// A position in a history tree.
+///
+/// Positions are ordered by branch first and then by the position in the branch.
+/// It is displayed as `branch:current`, the same way positions are shown when a history is displayed.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
-struct At {
-    branch: usize,
-    current: usize,
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct At {
+    /// The id of the branch.
+    pub branch: usize,
+    /// The position in the branch.
+    pub current: usize,
 }
 
 impl At {
-    fn new(branch: usize, current: usize) -> At {
+    /// Returns a new position.
+    pub fn new(branch: usize, current: usize) -> At {
         At { branch, current }
     }
 }
 
+impl fmt::Display for At {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.branch, self.current)
+    }
+}
+
+/// A handle to a slot connected to a record or history.
+///
+/// It is returned by `connect` and is used to `disconnect` the slot again.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Subscription(usize);
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 struct Slot<F> {
-    #[cfg_attr(feature = "serde", serde(default = "Option::default", skip))]
-    f: Option<F>,
+    #[cfg_attr(feature = "serde", serde(default = "Vec::new", skip))]
+    fs: Vec<(Subscription, F)>,
+    #[cfg_attr(feature = "serde", serde(default, skip))]
+    next: usize,
 }
 
-impl<F: FnMut(Signal)> Slot<F> {
-    fn emit(&mut self, signal: Signal) {
-        if let Some(ref mut f) = self.f {
-            f(signal);
-        }
+impl<F> Slot<F> {
+    fn connect(&mut self, f: F) -> Subscription {
+        let subscription = Subscription(self.next);
+        self.next += 1;
+        self.fs.push((subscription, f));
+        subscription
+    }
+
+    fn disconnect(&mut self, subscription: Subscription) -> Option<F> {
+        let i = self.fs.iter().position(|&(s, _)| s == subscription)?;
+        Some(self.fs.remove(i).1)
     }
 
-    fn emit_if(&mut self, cond: bool, signal: Signal) {
-        if cond {
-            self.emit(signal)
+    fn is_empty(&self) -> bool {
+        self.fs.is_empty()
+    }
+}
+
+impl<F: Observer> Slot<F> {
+    fn emit(&mut self, message: Message) {
+        for (_, f) in &mut self.fs {
+            f.receive(message.clone());
         }
     }
 }
 
 impl<F> Default for Slot<F> {
     fn default() -> Self {
-        Slot { f: None }
+        Slot {
+            fs: Vec::new(),
+            next: 0,
+        }
     }
 }
 
 impl<F> fmt::Debug for Slot<F> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.f {
-            Some(_) => f.pad("Slot { .. }"),
-            None => f.pad("Empty"),
+        if self.fs.is_empty() {
+            f.pad("Empty")
+        } else {
+            f.debug_list()
+                .entries(self.fs.iter().map(|(subscription, _)| subscription))
+                .finish()
         }
     }
 }
@@ -161,14 +421,36 @@ impl<F> fmt::Debug for Slot<F> {
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 struct Entry<C> {
     command: C,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    label: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    sealed: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    seq: usize,
     #[cfg(feature = "chrono")]
     timestamp: DateTime<Utc>,
 }
 
+impl<C: ToString> Entry<C> {
+    /// Returns the label of the entry if it has one, otherwise the text of the command.
+    fn text(&self) -> String {
+        match self.label {
+            Some(ref label) => label.clone(),
+            None => self.command.to_string(),
+        }
+    }
+}
+
 impl<C> From<C> for Entry<C> {
     fn from(command: C) -> Self {
         Entry {
             command,
+            label: None,
+            sealed: false,
+            seq: 0,
             #[cfg(feature = "chrono")]
             timestamp: Utc::now(),
         }
@@ -198,10 +480,17 @@ impl<C: Command> Command for Entry<C> {
             Merge::Annul => Merge::Annul,
         }
     }
+
+    fn size_hint(&self) -> usize {
+        self.command.size_hint()
+    }
 }
 
 impl<C: fmt::Display> fmt::Display for Entry<C> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        (&self.command as &dyn fmt::Display).fmt(f)
+        match self.label {
+            Some(ref label) => f.write_str(label),
+            None => (&self.command as &dyn fmt::Display).fmt(f),
+        }
     }
 }
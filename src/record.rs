@@ -1,22 +1,24 @@
 //! A record of commands.
 
-use crate::{format::Format, At, Command, Entry, History, Merge, Result, Signal, Slot};
+use crate::{
+    format::Format, At, Command, Entries, Entry, EntryRef, Group, History, Merge, Message,
+    Observer, QueueError, Result, Signal, Slot, Subscription,
+};
 use alloc::{
-    collections::VecDeque,
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
     string::{String, ToString},
     vec::Vec,
 };
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use core::{
     fmt::{self, Write},
+    mem,
     num::NonZeroUsize,
 };
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "chrono")]
-use {
-    chrono::{DateTime, TimeZone, Utc},
-    core::cmp::Ordering,
-};
 
 /// A record of commands.
 ///
@@ -26,6 +28,9 @@ use {
 /// The user can give the record a function that is called each time the state
 /// changes by using the [`builder`](struct.RecordBuilder.html).
 ///
+/// The record is `Send` if the commands, the target, and the slots are `Send`, and `Sync` if they are `Sync`.
+/// Use [`SharedRecord`](../struct.SharedRecord.html) to share a record between threads.
+///
 /// # Examples
 /// ```
 /// # use redo::{Command, Record};
@@ -73,8 +78,24 @@ pub struct Record<C: Command, F = fn(Signal)> {
     target: C::Target,
     current: usize,
     limit: NonZeroUsize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    budget: Option<usize>,
     pub(crate) saved: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) bookmarks: BTreeMap<String, usize>,
     pub(crate) slot: Slot<F>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    seq: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    evicted: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    checkpoints: usize,
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    merge_interval: Option<Duration>,
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    retention: Option<Duration>,
 }
 
 impl<C: Command> Record<C> {
@@ -118,16 +139,52 @@ impl<C: Command, F> Record<C, F> {
         self.limit.get()
     }
 
-    /// Sets how the signal should be handled when the state changes.
+    /// Returns the budget of the record if it has one.
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    /// Returns the total size of the commands in the record.
+    ///
+    /// See [`size_hint`](../trait.Command.html#method.size_hint) for more information.
+    pub fn size(&self) -> usize {
+        self.entries.iter().map(Entry::size_hint).sum()
+    }
+
+    /// Connects a slot that is called with the signal when the state changes.
     ///
-    /// The previous slot is returned if it exists.
-    pub fn connect(&mut self, slot: F) -> Option<F> {
-        self.slot.f.replace(slot)
+    /// All connected slots are called in the order they were connected.
+    /// The returned subscription can be used to disconnect the slot.
+    pub fn connect(&mut self, slot: F) -> Subscription {
+        self.slot.connect(slot)
     }
 
-    /// Removes and returns the slot if it exists.
-    pub fn disconnect(&mut self) -> Option<F> {
-        self.slot.f.take()
+    /// Removes and returns the slot with the subscription if it exists.
+    pub fn disconnect(&mut self, subscription: Subscription) -> Option<F> {
+        self.slot.disconnect(subscription)
+    }
+
+    /// Replaces the slots, returning the record with the new slots and the old slots.
+    #[cfg(feature = "std")]
+    pub(crate) fn replace_slot<G>(self, slot: Slot<G>) -> (Record<C, G>, Slot<F>) {
+        let record = Record {
+            entries: self.entries,
+            target: self.target,
+            current: self.current,
+            limit: self.limit,
+            budget: self.budget,
+            saved: self.saved,
+            bookmarks: self.bookmarks,
+            slot,
+            seq: self.seq,
+            evicted: self.evicted,
+            checkpoints: self.checkpoints,
+            #[cfg(feature = "chrono")]
+            merge_interval: self.merge_interval,
+            #[cfg(feature = "chrono")]
+            retention: self.retention,
+        };
+        (record, self.slot)
     }
 
     /// Returns `true` if the record can undo.
@@ -142,7 +199,7 @@ impl<C: Command, F> Record<C, F> {
 
     /// Returns `true` if the target is in a saved state, `false` otherwise.
     pub fn is_saved(&self) -> bool {
-        self.saved.map_or(false, |saved| saved == self.current())
+        self.saved == Some(self.current())
     }
 
     /// Returns the position of the current command.
@@ -150,18 +207,80 @@ impl<C: Command, F> Record<C, F> {
         self.current
     }
 
-    /// Returns a queue.
-    pub fn queue(&mut self) -> Queue<C, F> {
-        Queue::from(self)
+    /// Returns the position of the bookmark if it exists.
+    pub fn bookmark(&self, name: &str) -> Option<usize> {
+        self.bookmarks.get(name).copied()
     }
 
-    /// Returns a checkpoint.
-    pub fn checkpoint(&mut self) -> Checkpoint<C, F> {
-        Checkpoint::from(self)
+    /// Returns `true` if the target is at the bookmark, `false` otherwise.
+    pub fn is_at_bookmark(&self, name: &str) -> bool {
+        self.bookmark(name) == Some(self.current)
+    }
+
+    /// Returns an iterator over the names and positions of the bookmarks, ordered by name.
+    pub fn bookmarks(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.bookmarks
+            .iter()
+            .map(|(name, &current)| (name.as_str(), current))
+    }
+
+    /// Returns the command at `index` if it exists.
+    pub fn get(&self, index: usize) -> Option<EntryRef<'_, C>> {
+        self.entries
+            .get(index)
+            .map(|entry| EntryRef::new(index, entry, self.current, self.saved))
+    }
+
+    /// Returns an iterator over the commands in the record, starting with the oldest.
+    pub fn entries(&self) -> Entries<'_, C> {
+        Entries::new(self.entries.iter(), 0, self.current, self.saved)
+    }
+
+    /// Returns an iterator over the commands that can be undone, starting with the oldest.
+    pub fn undo_stack(&self) -> Entries<'_, C> {
+        Entries::new(
+            self.entries.range(..self.current),
+            0,
+            self.current,
+            self.saved,
+        )
+    }
+
+    /// Returns an iterator over the commands that can be redone,
+    /// starting with the one that will be redone in the next call to `redo`.
+    pub fn redo_stack(&self) -> Entries<'_, C> {
+        Entries::new(
+            self.entries.range(self.current..),
+            self.current,
+            self.current,
+            self.saved,
+        )
+    }
+
+    /// Marks the current command as closed to merging.
+    ///
+    /// Commands applied after this will not be merged into it,
+    /// even if it is undone and redone later.
+    pub fn seal(&mut self) {
+        if let Some(entry) = self.current.checked_sub(1).map(|i| &mut self.entries[i]) {
+            entry.sealed = true;
+        }
+    }
+
+    /// Prevents the next command from being merged into the current command.
+    ///
+    /// This is the same as [`seal`](struct.Record.html#method.seal).
+    pub fn break_merge(&mut self) {
+        self.seal();
+    }
+
+    /// Returns a queue.
+    pub fn queue(&mut self) -> Queue<'_, C, F> {
+        Queue::from(self)
     }
 
     /// Returns a structure for configurable formatting of the record.
-    pub fn display(&self) -> Display<C, F> {
+    pub fn display(&self) -> Display<'_, C, F> {
         Display::from(self)
     }
 
@@ -183,33 +302,153 @@ impl<C: Command, F> Record<C, F> {
     }
 }
 
-impl<C: Command, F: FnMut(Signal)> Record<C, F> {
+impl<C: Command, F: Observer> Record<C, F> {
+    /// Returns a checkpoint.
+    ///
+    /// The changes made through the checkpoint are canceled if it is dropped without being committed.
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, C, F> {
+        Checkpoint::from(self)
+    }
+
+    /// Returns a checkpoint with the label.
+    ///
+    /// When committed with [`commit_squashed`] the changes are stored as a single entry with the label,
+    /// which is shown by [`undo_text`], [`redo_text`], and the display structure.
+    /// When committed with [`commit`] the label is given to the last command applied through the checkpoint.
+    /// The labeled entry is sealed, so later commands are not merged into it.
+    ///
+    /// # Examples
+    /// ```
+    /// # use redo::{Command, Group, Record};
+    /// # struct Add(char);
+    /// # impl Command for Add {
+    /// #     type Target = String;
+    /// #     type Error = &'static str;
+    /// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+    /// #         s.push(self.0);
+    /// #         Ok(())
+    /// #     }
+    /// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+    /// #         self.0 = s.pop().ok_or("s is empty")?;
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// # enum Edit {
+    /// #     Add(Add),
+    /// #     Group(Group<Edit>),
+    /// # }
+    /// # impl Command for Edit {
+    /// #     type Target = String;
+    /// #     type Error = &'static str;
+    /// #     fn apply(&mut self, s: &mut String) -> redo::Result<Edit> {
+    /// #         match self {
+    /// #             Edit::Add(add) => add.apply(s),
+    /// #             Edit::Group(group) => group.apply(s),
+    /// #         }
+    /// #     }
+    /// #     fn undo(&mut self, s: &mut String) -> redo::Result<Edit> {
+    /// #         match self {
+    /// #             Edit::Add(add) => add.undo(s),
+    /// #             Edit::Group(group) => group.undo(s),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # impl From<Group<Edit>> for Edit {
+    /// #     fn from(group: Group<Edit>) -> Edit {
+    /// #         Edit::Group(group)
+    /// #     }
+    /// # }
+    /// # impl std::fmt::Display for Edit {
+    /// #     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    /// #         match self {
+    /// #             Edit::Add(add) => write!(f, "Add '{}'", add.0),
+    /// #             Edit::Group(_) => f.write_str("Group"),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> redo::Result<Add> {
+    /// let mut record = Record::default();
+    /// let mut transaction = record.transaction("Add 'abc'");
+    /// transaction.apply(Edit::Add(Add('a')))?;
+    /// transaction.apply(Edit::Add(Add('b')))?;
+    /// transaction.apply(Edit::Add(Add('c')))?;
+    /// transaction.commit_squashed();
+    /// assert_eq!(record.len(), 1);
+    /// assert_eq!(record.undo_text().unwrap(), "Add 'abc'");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`commit`]: record/struct.Checkpoint.html#method.commit
+    /// [`commit_squashed`]: record/struct.Checkpoint.html#method.commit_squashed
+    /// [`undo_text`]: struct.Record.html#method.undo_text
+    /// [`redo_text`]: struct.Record.html#method.redo_text
+    pub fn transaction(&mut self, label: impl Into<String>) -> Checkpoint<'_, C, F> {
+        let mut checkpoint = Checkpoint::from(self);
+        checkpoint.set_label(label);
+        checkpoint
+    }
+
     /// Marks the target as currently being in a saved or unsaved state.
     pub fn set_saved(&mut self, saved: bool) {
         let was_saved = self.is_saved();
         if saved {
             self.saved = Some(self.current());
-            self.slot.emit_if(!was_saved, Signal::Saved(true));
+            self.emit_if(!was_saved, Signal::Saved(true));
         } else {
             self.saved = None;
-            self.slot.emit_if(was_saved, Signal::Saved(false));
+            self.emit_if(was_saved, Signal::Saved(false));
         }
     }
 
+    /// Marks the current position with a bookmark named `name`.
+    ///
+    /// If the bookmark already exists it is moved to the current position.
+    /// Like the saved state, commands will not be merged into the command at a bookmark.
+    pub fn set_bookmark(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        let was_at = self.is_at_bookmark(&name);
+        let signal = Signal::Bookmark(name.as_str().into(), true);
+        self.bookmarks.insert(name, self.current);
+        self.emit_if(!was_at, signal);
+    }
+
+    /// Removes the bookmark and returns its position if it existed.
+    pub fn remove_bookmark(&mut self, name: &str) -> Option<usize> {
+        let bookmark = self.bookmarks.remove(name)?;
+        self.emit_if(
+            bookmark == self.current,
+            Signal::Bookmark(name.into(), false),
+        );
+        Some(bookmark)
+    }
+
     /// Revert the changes done to the target since the saved state.
     pub fn revert(&mut self) -> Option<Result<C>> {
         self.saved.and_then(|saved| self.go_to(saved))
     }
 
+    /// Goes to the bookmark with the name, see [`go_to`](struct.Record.html#method.go_to).
+    ///
+    /// Returns `None` if there is no bookmark with the name.
+    pub fn go_to_bookmark(&mut self, name: &str) -> Option<Result<C>> {
+        self.bookmark(name).and_then(|current| self.go_to(current))
+    }
+
     /// Removes all commands from the record without undoing them.
     pub fn clear(&mut self) {
         let could_undo = self.can_undo();
         let could_redo = self.can_redo();
         self.entries.clear();
         self.saved = if self.is_saved() { Some(0) } else { None };
+        let current = self.current;
+        self.bookmarks.retain(|_, bookmark| *bookmark == current);
+        self.bookmarks
+            .values_mut()
+            .for_each(|bookmark| *bookmark = 0);
         self.current = 0;
-        self.slot.emit_if(could_undo, Signal::Undo(false));
-        self.slot.emit_if(could_redo, Signal::Redo(false));
+        self.emit_if(could_undo, Signal::Undo(false));
+        self.emit_if(could_redo, Signal::Redo(false));
     }
 
     /// Pushes the command on top of the record and executes its [`apply`] method.
@@ -219,50 +458,280 @@ impl<C: Command, F: FnMut(Signal)> Record<C, F> {
     ///
     /// [`apply`]: trait.Command.html#tymethod.apply
     pub fn apply(&mut self, command: C) -> Result<C> {
-        self.__apply(command).map(|_| ())
+        self.__apply(command, true).map(|_| ())
     }
 
+    /// Applies the command, merging it into the current command only if `merge` is `true`.
     pub(crate) fn __apply(
         &mut self,
         mut command: C,
-    ) -> core::result::Result<(bool, VecDeque<Entry<C>>), C::Error> {
+        merge: bool,
+    ) -> core::result::Result<Applied<C>, C::Error> {
         command.apply(&mut self.target)?;
         let current = self.current();
         let could_undo = self.can_undo();
         let could_redo = self.can_redo();
         let was_saved = self.is_saved();
+        let marks = self.marks();
+        #[cfg(feature = "chrono")]
+        let now = Utc::now();
         // Pop off all elements after len from record.
         let tail = self.entries.split_off(current);
-        // Check if the saved state was popped off.
+        // Check if the saved state or any bookmarks were popped off.
         self.saved = self.saved.filter(|&saved| saved <= current);
-        // Try to merge commands unless the target is in a saved state.
+        self.bookmarks
+            .retain(|_, &mut bookmark| bookmark <= current);
+        // Try to merge commands unless the target is in a saved state, at a bookmark,
+        // or the current command is sealed.
+        let can_merge =
+            merge && !was_saved && !self.bookmarks.values().any(|&bookmark| bookmark == current);
+        #[cfg(feature = "chrono")]
+        let can_merge = can_merge
+            && match (self.merge_interval, self.entries.back()) {
+                (Some(interval), Some(last)) => now - last.timestamp <= interval,
+                _ => true,
+            };
         let merged = match self.entries.back_mut() {
-            Some(ref mut last) if !was_saved => last.command.merge(command),
+            Some(ref mut last) if can_merge && !last.sealed => last.command.merge(command),
             _ => Merge::No(command),
         };
-        let merged_or_annulled = match merged {
-            Merge::Yes => true,
+        self.seq += 1;
+        // The signal for the operation and the index of the affected command.
+        let (merged_or_annulled, signal, index): (_, fn(usize) -> Signal, _) = match merged {
+            Merge::Yes => {
+                let last = self.entries.back_mut().unwrap();
+                last.seq = self.seq;
+                #[cfg(feature = "chrono")]
+                {
+                    last.timestamp = now;
+                }
+                (true, Signal::Merged, self.current - 1)
+            }
             Merge::Annul => {
                 self.entries.pop_back();
-                true
+                self.current -= 1;
+                (true, Signal::Annulled, self.current)
             }
             // If commands are not merged or annulled push it onto the record.
             Merge::No(command) => {
-                // If limit is reached, pop off the first command.
-                if self.limit() == self.current() {
-                    self.entries.pop_front();
-                    self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
-                } else {
-                    self.current += 1;
+                let mut entry = Entry::from(command);
+                entry.seq = self.seq;
+                self.entries.push_back(entry);
+                self.current += 1;
+                (false, Signal::Applied, self.current - 1)
+            }
+        };
+        let evicted = self.evict();
+        let can_undo = self.can_undo();
+        let is_saved = self.is_saved();
+        self.emit_if(could_redo, Signal::Redo(false));
+        self.emit_if(evicted > 0, Signal::Evicted(evicted));
+        // The command the signal is about is never evicted.
+        if let Some(index) = index.checked_sub(evicted) {
+            self.emit(signal(index));
+        }
+        self.emit_if(could_undo != can_undo, Signal::Undo(can_undo));
+        self.emit_if(was_saved != is_saved, Signal::Saved(is_saved));
+        self.emit_marks(marks);
+        Ok(Applied {
+            merged: merged_or_annulled,
+            evicted,
+            tail,
+        })
+    }
+
+    /// Removes the oldest commands until the record is within its limit, retention and budget,
+    /// and returns the number of commands that were removed.
+    ///
+    /// The current command is never removed, and nothing is removed while a checkpoint is open
+    /// so the checkpoint can be canceled.
+    fn evict(&mut self) -> usize {
+        if self.checkpoints > 0 {
+            return 0;
+        }
+        let mut evicted = self.current.saturating_sub(self.limit());
+        #[cfg(feature = "chrono")]
+        {
+            if let Some(retention) = self.retention {
+                let older = self.count_older_than(&(Utc::now() - retention));
+                evicted = evicted.max(older.min(self.current.saturating_sub(1)));
+            }
+        }
+        if let Some(budget) = self.budget {
+            let mut size: usize = self
+                .entries
+                .iter()
+                .skip(evicted)
+                .map(Entry::size_hint)
+                .sum();
+            while size > budget && evicted + 1 < self.current {
+                size -= self.entries[evicted].size_hint();
+                evicted += 1;
+            }
+        }
+        debug_assert!(evicted < self.current.max(1));
+        self.rm_front(evicted);
+        evicted
+    }
+
+    /// Removes the `n` first commands.
+    fn rm_front(&mut self, n: usize) {
+        self.entries.drain(..n);
+        self.current -= n;
+        self.evicted += n;
+        self.saved = self.saved.and_then(|saved| saved.checked_sub(n));
+        self.bookmarks
+            .retain(|_, bookmark| match bookmark.checked_sub(n) {
+                Some(current) => {
+                    *bookmark = current;
+                    true
+                }
+                None => false,
+            });
+    }
+
+    pub(crate) fn begin_checkpoint(&mut self) {
+        self.checkpoints += 1;
+    }
+
+    /// Closes a checkpoint, and evicts the commands that were kept while checkpoints were open
+    /// when the last one is closed. Returns the number of commands that were removed.
+    pub(crate) fn end_checkpoint(&mut self) -> usize {
+        self.checkpoints -= 1;
+        let evicted = self.evict();
+        self.emit_if(evicted > 0, Signal::Evicted(evicted));
+        evicted
+    }
+
+    /// Returns the names of the bookmarks at the current position.
+    ///
+    /// Nothing is returned if there is no slot or bookmark, since no signals will be emitted.
+    fn marks(&self) -> Vec<String> {
+        if self.slot.is_empty() || self.bookmarks.is_empty() {
+            return Vec::new();
+        }
+        self.bookmarks
+            .iter()
+            .filter(|&(_, &bookmark)| bookmark == self.current)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Emits the bookmarks the target has left or arrived at since `old` was returned by `marks`.
+    fn emit_marks(&mut self, old: Vec<String>) {
+        let new = self.marks();
+        for name in &old {
+            if !new.contains(name) {
+                self.emit(Signal::Bookmark(name.as_str().into(), false));
+            }
+        }
+        for name in &new {
+            if !old.contains(name) {
+                self.emit(Signal::Bookmark(name.as_str().into(), true));
+            }
+        }
+    }
+
+    /// Returns the number of commands before the current position that were made before `time`.
+    #[cfg(feature = "chrono")]
+    fn count_older_than(&self, time: &DateTime<Utc>) -> usize {
+        self.entries
+            .iter()
+            .take(self.current)
+            .take_while(|entry| entry.timestamp < *time)
+            .count()
+    }
+
+    /// Collapses the commands between `from` and the current position into a single entry.
+    ///
+    /// The commands are merged if possible, otherwise they are stored in a group.
+    pub(crate) fn squash(&mut self, from: usize, label: Option<String>)
+    where
+        C: From<Group<C>>,
+    {
+        let current = self.current();
+        if from + 1 < current {
+            self.__squash(from);
+        }
+        if self.current() == from + 1 {
+            if let Some(label) = label {
+                self.set_label(label);
+            }
+        }
+    }
+
+    /// Gives the current command the label and seals it, so later commands are not merged into it.
+    pub(crate) fn set_label(&mut self, label: String) {
+        let entry = &mut self.entries[self.current - 1];
+        entry.label = Some(label);
+        entry.sealed = true;
+    }
+
+    fn __squash(&mut self, from: usize)
+    where
+        C: From<Group<C>>,
+    {
+        let current = self.current();
+        let could_undo = self.can_undo();
+        let was_saved = self.is_saved();
+        let mut tail = self.entries.split_off(current);
+        let mut commands = Vec::with_capacity(current - from);
+        let sealed = self.entries.back().unwrap().sealed;
+        let seq = self.entries.back().unwrap().seq;
+        #[cfg(feature = "chrono")]
+        let timestamp = self.entries.back().unwrap().timestamp;
+        for entry in self.entries.drain(from..) {
+            let merged = match commands.last_mut() {
+                Some(last) => C::merge(last, entry.command),
+                None => Merge::No(entry.command),
+            };
+            match merged {
+                Merge::Yes => (),
+                Merge::Annul => {
+                    commands.pop();
                 }
-                self.entries.push_back(Entry::from(command));
-                false
+                Merge::No(command) => commands.push(command),
+            }
+        }
+        let command = match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(C::from(Group::from(commands))),
+        };
+        if let Some(command) = command {
+            let mut entry = Entry::from(command);
+            entry.sealed = sealed;
+            entry.seq = seq;
+            #[cfg(feature = "chrono")]
+            {
+                entry.timestamp = timestamp;
+            }
+            self.entries.push_back(entry);
+        }
+        self.current = self.entries.len();
+        self.entries.append(&mut tail);
+        let removed = current - self.current;
+        let shift = |position: usize| {
+            if position <= from {
+                Some(position)
+            } else if position >= current {
+                Some(position - removed)
+            } else {
+                None
             }
         };
-        self.slot.emit_if(could_redo, Signal::Redo(false));
-        self.slot.emit_if(!could_undo, Signal::Undo(true));
-        self.slot.emit_if(was_saved, Signal::Saved(false));
-        Ok((merged_or_annulled, tail))
+        self.saved = self.saved.and_then(shift);
+        self.bookmarks.retain(|_, bookmark| match shift(*bookmark) {
+            Some(position) => {
+                *bookmark = position;
+                true
+            }
+            None => false,
+        });
+        let can_undo = self.can_undo();
+        let is_saved = self.is_saved();
+        self.emit_if(could_undo != can_undo, Signal::Undo(can_undo));
+        self.emit_if(was_saved != is_saved, Signal::Saved(is_saved));
     }
 
     /// Calls the [`undo`] method for the active command and sets
@@ -277,15 +746,17 @@ impl<C: Command, F: FnMut(Signal)> Record<C, F> {
             return Ok(());
         }
         let was_saved = self.is_saved();
+        let marks = self.marks();
         let old = self.current();
         self.entries[self.current - 1].undo(&mut self.target)?;
         self.current -= 1;
         let len = self.len();
         let is_saved = self.is_saved();
-        self.slot.emit_if(old == len, Signal::Redo(true));
-        self.slot.emit_if(old == 1, Signal::Undo(false));
-        self.slot
-            .emit_if(was_saved != is_saved, Signal::Saved(is_saved));
+        self.emit(Signal::Undone(self.current));
+        self.emit_if(old == len, Signal::Redo(true));
+        self.emit_if(old == 1, Signal::Undo(false));
+        self.emit_if(was_saved != is_saved, Signal::Saved(is_saved));
+        self.emit_marks(marks);
         Ok(())
     }
 
@@ -301,15 +772,17 @@ impl<C: Command, F: FnMut(Signal)> Record<C, F> {
             return Ok(());
         }
         let was_saved = self.is_saved();
+        let marks = self.marks();
         let old = self.current();
         self.entries[self.current].redo(&mut self.target)?;
         self.current += 1;
         let len = self.len();
         let is_saved = self.is_saved();
-        self.slot.emit_if(old == len - 1, Signal::Redo(false));
-        self.slot.emit_if(old == 0, Signal::Undo(true));
-        self.slot
-            .emit_if(was_saved != is_saved, Signal::Saved(is_saved));
+        self.emit(Signal::Redone(old));
+        self.emit_if(old == len - 1, Signal::Redo(false));
+        self.emit_if(old == 0, Signal::Undo(true));
+        self.emit_if(was_saved != is_saved, Signal::Saved(is_saved));
+        self.emit_marks(marks);
         Ok(())
     }
 
@@ -324,61 +797,168 @@ impl<C: Command, F: FnMut(Signal)> Record<C, F> {
         if current > self.len() {
             return None;
         }
-        let could_undo = self.can_undo();
-        let could_redo = self.can_redo();
-        let was_saved = self.is_saved();
-        // Temporarily remove slot so they are not called each iteration.
-        let f = self.slot.f.take();
-        // Decide if we need to undo or redo to reach current.
-        let apply = if current > self.current() {
-            Record::redo
-        } else {
-            Record::undo
-        };
-        while self.current() != current {
-            if let Err(err) = apply(self) {
-                self.slot.f = f;
-                return Some(Err(err));
+        Some(self.batch(|record| {
+            // Decide if we need to undo or redo to reach current.
+            let apply = if current > record.current() {
+                Record::redo
+            } else {
+                Record::undo
+            };
+            while record.current() != current {
+                apply(record)?;
             }
+            Ok(())
+        }))
+    }
+
+    pub(crate) fn emit(&mut self, signal: Signal) {
+        let message = Message {
+            signal,
+            current: self.current,
+            len: self.len(),
+        };
+        self.slot.emit(message);
+    }
+
+    pub(crate) fn emit_if(&mut self, cond: bool, signal: Signal) {
+        if cond {
+            self.emit(signal);
+        }
+    }
+
+    /// Calls `f` without emitting any signals, and then emits the commands that were evicted
+    /// and applied, and the net changes to the undo, redo, saved and bookmark states.
+    ///
+    /// Used by operations made of several steps so the slots are not called for each step.
+    pub(crate) fn batch<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let batch = self.begin_batch();
+        let t = f(self);
+        self.end_batch(batch);
+        t
+    }
+
+    pub(crate) fn begin_batch(&mut self) -> Batch<F> {
+        Batch {
+            could_undo: self.can_undo(),
+            could_redo: self.can_redo(),
+            was_saved: self.is_saved(),
+            marks: self.marks(),
+            seq: self.seq,
+            evicted: self.evicted,
+            // Temporarily remove the slots so they are not called by the steps.
+            fs: mem::take(&mut self.slot.fs),
+        }
+    }
+
+    pub(crate) fn end_batch(&mut self, batch: Batch<F>) {
+        // Add the slots back, keeping any slots connected during the batch.
+        let connected = mem::replace(&mut self.slot.fs, batch.fs);
+        self.slot.fs.extend(connected);
+        let evicted = self.evicted - batch.evicted;
+        self.emit_if(evicted > 0, Signal::Evicted(evicted));
+        // The commands applied in the batch that have not been undone or evicted.
+        let seq = batch.seq;
+        let applied: Vec<_> = (0..self.current)
+            .filter(|&i| self.entries[i].seq > seq)
+            .collect();
+        for i in applied {
+            self.emit(Signal::Applied(i));
         }
-        // Add slot back.
-        self.slot.f = f;
         let can_undo = self.can_undo();
         let can_redo = self.can_redo();
         let is_saved = self.is_saved();
-        self.slot
-            .emit_if(could_undo != can_undo, Signal::Undo(can_undo));
-        self.slot
-            .emit_if(could_redo != can_redo, Signal::Redo(can_redo));
-        self.slot
-            .emit_if(was_saved != is_saved, Signal::Saved(is_saved));
-        Some(Ok(()))
+        self.emit_if(batch.could_undo != can_undo, Signal::Undo(can_undo));
+        self.emit_if(batch.could_redo != can_redo, Signal::Redo(can_redo));
+        self.emit_if(batch.was_saved != is_saved, Signal::Saved(is_saved));
+        self.emit_marks(batch.marks);
     }
 
     /// Go back or forward in the record to the command that was made closest to the datetime provided.
+    ///
+    /// If a command was made exactly at `to`, the record goes to the state before it.
     #[cfg(feature = "chrono")]
     pub fn time_travel(&mut self, to: &DateTime<impl TimeZone>) -> Option<Result<C>> {
+        if self.entries.is_empty() {
+            return None;
+        }
         let to = to.with_timezone(&Utc);
-        let current = match self.entries.as_slices() {
-            ([], []) => return None,
-            (start, []) => match start.binary_search_by(|entry| entry.timestamp.cmp(&to)) {
-                Ok(current) | Err(current) => current,
-            },
-            ([], end) => match end.binary_search_by(|entry| entry.timestamp.cmp(&to)) {
-                Ok(current) | Err(current) => current,
-            },
-            (start, end) => match start.last().unwrap().timestamp.cmp(&to) {
-                Ordering::Less => match start.binary_search_by(|entry| entry.timestamp.cmp(&to)) {
-                    Ok(current) | Err(current) => current,
-                },
-                Ordering::Equal => start.len(),
-                Ordering::Greater => match end.binary_search_by(|entry| entry.timestamp.cmp(&to)) {
-                    Ok(current) | Err(current) => start.len() + current,
-                },
-            },
-        };
+        let current = self.entries.partition_point(|entry| entry.timestamp < to);
         self.go_to(current)
     }
+
+    /// Goes back in the record by `duration`, measured from the time stamp of the current command,
+    /// and returns the number of commands that were undone.
+    ///
+    /// If no command has been applied, the duration is measured from the oldest command.
+    ///
+    /// # Errors
+    /// If an error occur when executing [`undo`] the error is returned.
+    ///
+    /// [`undo`]: ../trait.Command.html#tymethod.undo
+    #[cfg(feature = "chrono")]
+    pub fn travel_back(&mut self, duration: Duration) -> core::result::Result<usize, C::Error> {
+        match self.timestamp() {
+            Some(timestamp) => {
+                let to = timestamp - duration;
+                let current = self.entries.partition_point(|entry| entry.timestamp <= to);
+                self.travel(current.min(self.current))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Goes forward in the record by `duration`, measured from the time stamp of the current command,
+    /// and returns the number of commands that were redone.
+    ///
+    /// If no command has been applied, the duration is measured from the oldest command.
+    ///
+    /// # Errors
+    /// If an error occur when executing [`redo`] the error is returned.
+    ///
+    /// [`redo`]: ../trait.Command.html#method.redo
+    #[cfg(feature = "chrono")]
+    pub fn travel_forward(&mut self, duration: Duration) -> core::result::Result<usize, C::Error> {
+        match self.timestamp() {
+            Some(timestamp) => {
+                let to = timestamp + duration;
+                let current = self.entries.partition_point(|entry| entry.timestamp <= to);
+                self.travel(current.max(self.current))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the time stamp of the current command, or of the oldest command if none has been applied.
+    #[cfg(feature = "chrono")]
+    pub(crate) fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let index = self.current.saturating_sub(1);
+        self.entries.get(index).map(|entry| entry.timestamp)
+    }
+
+    #[cfg(feature = "chrono")]
+    fn travel(&mut self, current: usize) -> core::result::Result<usize, C::Error> {
+        let steps = self.current.abs_diff(current);
+        self.go_to(current).unwrap()?;
+        Ok(steps)
+    }
+
+    /// Removes the commands that can be undone and were made before `time`,
+    /// and returns the number of commands that were removed.
+    ///
+    /// The commands that can be redone are kept.
+    #[cfg(feature = "chrono")]
+    pub fn prune_older_than(&mut self, time: &DateTime<impl TimeZone>) -> usize {
+        let could_undo = self.can_undo();
+        let was_saved = self.is_saved();
+        let pruned = self.count_older_than(&time.with_timezone(&Utc));
+        self.rm_front(pruned);
+        let can_undo = self.can_undo();
+        let is_saved = self.is_saved();
+        self.emit_if(pruned > 0, Signal::Evicted(pruned));
+        self.emit_if(could_undo != can_undo, Signal::Undo(can_undo));
+        self.emit_if(was_saved != is_saved, Signal::Saved(is_saved));
+        pruned
+    }
 }
 
 impl<C: Command + ToString, F> Record<C, F> {
@@ -387,7 +967,7 @@ impl<C: Command + ToString, F> Record<C, F> {
     /// [`undo`]: struct.Record.html#method.undo
     pub fn undo_text(&self) -> Option<String> {
         if self.can_undo() {
-            Some(self.entries[self.current - 1].command.to_string())
+            Some(self.entries[self.current - 1].text())
         } else {
             None
         }
@@ -398,7 +978,7 @@ impl<C: Command + ToString, F> Record<C, F> {
     /// [`redo`]: struct.Record.html#method.redo
     pub fn redo_text(&self) -> Option<String> {
         if self.can_redo() {
-            Some(self.entries[self.current].command.to_string())
+            Some(self.entries[self.current].text())
         } else {
             None
         }
@@ -414,7 +994,7 @@ where
     }
 }
 
-impl<C: Command, F: FnMut(Signal)> From<History<C, F>> for Record<C, F> {
+impl<C: Command, F: Observer> From<History<C, F>> for Record<C, F> {
     fn from(history: History<C, F>) -> Record<C, F> {
         history.record
     }
@@ -426,24 +1006,58 @@ where
     C::Target: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Record")
+        let mut debug = f.debug_struct("Record");
+        debug
             .field("entries", &self.entries)
             .field("target", &self.target)
             .field("current", &self.current)
             .field("limit", &self.limit)
+            .field("budget", &self.budget)
             .field("saved", &self.saved)
+            .field("bookmarks", &self.bookmarks)
             .field("slot", &self.slot)
-            .finish()
+            .field("seq", &self.seq);
+        #[cfg(feature = "chrono")]
+        debug
+            .field("merge_interval", &self.merge_interval)
+            .field("retention", &self.retention);
+        debug.finish()
     }
 }
 
+/// The changes made to a record when a command is applied.
+pub(crate) struct Applied<C> {
+    /// If the command was merged with or annulled the previous command.
+    pub(crate) merged: bool,
+    /// The number of commands removed because the limit or budget was exceeded.
+    pub(crate) evicted: usize,
+    /// The commands that could be redone before the command was applied.
+    pub(crate) tail: VecDeque<Entry<C>>,
+}
+
+/// The state of a record when a batch began, see `Record::batch`.
+pub(crate) struct Batch<F> {
+    could_undo: bool,
+    could_redo: bool,
+    was_saved: bool,
+    marks: Vec<String>,
+    seq: usize,
+    evicted: usize,
+    fs: Vec<(Subscription, F)>,
+}
+
 /// Builder for a record.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug)]
 pub struct Builder {
     capacity: usize,
     limit: NonZeroUsize,
+    budget: Option<usize>,
     saved: bool,
+    #[cfg(feature = "chrono")]
+    merge_interval: Option<Duration>,
+    #[cfg(feature = "chrono")]
+    retention: Option<Duration>,
 }
 
 impl Builder {
@@ -451,8 +1065,13 @@ impl Builder {
     pub fn new() -> Builder {
         Builder {
             capacity: 0,
-            limit: NonZeroUsize::new(usize::max_value()).unwrap(),
+            limit: NonZeroUsize::new(usize::MAX).unwrap(),
+            budget: None,
             saved: true,
+            #[cfg(feature = "chrono")]
+            merge_interval: None,
+            #[cfg(feature = "chrono")]
+            retention: None,
         }
     }
 
@@ -471,6 +1090,19 @@ impl Builder {
         self
     }
 
+    /// Sets the `budget` of the record.
+    ///
+    /// When the total [size] of the commands exceeds the budget,
+    /// the oldest commands are removed until it fits again.
+    /// The current command is always kept, even if it is larger than the budget.
+    /// By default there is no budget.
+    ///
+    /// [size]: ../trait.Command.html#method.size_hint
+    pub fn budget(&mut self, budget: usize) -> &mut Builder {
+        self.budget = Some(budget);
+        self
+    }
+
     /// Sets if the target is initially in a saved state.
     /// By default the target is in a saved state.
     pub fn saved(&mut self, saved: bool) -> &mut Builder {
@@ -478,27 +1110,65 @@ impl Builder {
         self
     }
 
+    /// Sets the interval in which a command must be applied after the previous command
+    /// for them to be merged.
+    ///
+    /// The time stamp of an entry is updated when a command is merged into it,
+    /// so the interval is measured from the last command that was merged.
+    /// By default there is no interval.
+    ///
+    /// Requires the `chrono` feature to be enabled.
+    #[cfg(feature = "chrono")]
+    pub fn merge_interval(&mut self, interval: Duration) -> &mut Builder {
+        self.merge_interval = Some(interval);
+        self
+    }
+
+    /// Sets how long the commands that can be undone are kept.
+    ///
+    /// When a command is applied, the commands that are older than the retention are removed,
+    /// except the applied command itself.
+    /// See [`prune_older_than`](struct.Record.html#method.prune_older_than) for removing them manually.
+    /// By default the commands are kept regardless of their age.
+    ///
+    /// Requires the `chrono` feature to be enabled.
+    #[cfg(feature = "chrono")]
+    pub fn retention(&mut self, retention: Duration) -> &mut Builder {
+        self.retention = Some(retention);
+        self
+    }
+
     /// Builds the record.
     pub fn build<C: Command>(&self, target: C::Target) -> Record<C> {
-        Record {
-            entries: VecDeque::with_capacity(self.capacity),
-            target,
-            current: 0,
-            limit: self.limit,
-            saved: if self.saved { Some(0) } else { None },
-            slot: Slot::default(),
-        }
+        self.__build(target, Slot::default())
     }
 
     /// Builds the record with the slot.
+    ///
+    /// Use [`connect`](struct.Record.html#method.connect) instead if the slot needs to be disconnected later.
     pub fn build_with<C: Command, F>(&self, target: C::Target, slot: F) -> Record<C, F> {
+        let mut record = self.__build(target, Slot::default());
+        record.slot.connect(slot);
+        record
+    }
+
+    fn __build<C: Command, F>(&self, target: C::Target, slot: Slot<F>) -> Record<C, F> {
         Record {
             entries: VecDeque::with_capacity(self.capacity),
             target,
             current: 0,
             limit: self.limit,
+            budget: self.budget,
             saved: if self.saved { Some(0) } else { None },
-            slot: Slot { f: Some(slot) },
+            bookmarks: BTreeMap::new(),
+            slot,
+            seq: 0,
+            evicted: 0,
+            checkpoints: 0,
+            #[cfg(feature = "chrono")]
+            merge_interval: self.merge_interval,
+            #[cfg(feature = "chrono")]
+            retention: self.retention,
         }
     }
 
@@ -557,7 +1227,7 @@ enum QueueCommand<C> {
 /// queue.apply(Add('b'));
 /// queue.apply(Add('c'));
 /// assert_eq!(queue.target(), "");
-/// queue.commit()?;
+/// queue.commit().map_err(|err| err.error)?;
 /// assert_eq!(record.target(), "abc");
 /// # Ok(())
 /// # }
@@ -567,7 +1237,7 @@ pub struct Queue<'a, C: Command, F> {
     commands: Vec<QueueCommand<C>>,
 }
 
-impl<C: Command, F: FnMut(Signal)> Queue<'_, C, F> {
+impl<C: Command, F: Observer> Queue<'_, C, F> {
     /// Queues an `apply` action.
     pub fn apply(&mut self, command: C) {
         self.commands.push(QueueCommand::Apply(command));
@@ -585,29 +1255,42 @@ impl<C: Command, F: FnMut(Signal)> Queue<'_, C, F> {
 
     /// Applies the queued commands.
     ///
+    /// The queued commands are not merged with other commands.
+    ///
     /// # Errors
-    /// If an error occurs, it stops applying the commands and returns the error.
-    pub fn commit(self) -> Result<C> {
-        for command in self.commands {
-            match command {
-                QueueCommand::Apply(command) => self.record.apply(command)?,
-                QueueCommand::Undo => self.record.undo()?,
-                QueueCommand::Redo => self.record.redo()?,
+    /// If an error occurs, it stops applying the commands, cancels the changes made by the commit,
+    /// and returns the error together with the index of the action that failed.
+    /// Errors that occur while canceling the changes are ignored.
+    pub fn commit(self) -> core::result::Result<(), QueueError<C::Error>> {
+        let commands = self.commands;
+        self.record.batch(|record| {
+            let mut checkpoint = record.checkpoint();
+            for (index, command) in commands.into_iter().enumerate() {
+                let result = match command {
+                    QueueCommand::Apply(command) => checkpoint.apply(command),
+                    QueueCommand::Undo => checkpoint.undo(),
+                    QueueCommand::Redo => checkpoint.redo(),
+                };
+                if let Err(error) = result {
+                    let _ = checkpoint.cancel();
+                    return Err(QueueError { index, error });
+                }
             }
-        }
-        Ok(())
+            checkpoint.commit();
+            Ok(())
+        })
     }
 
     /// Cancels the queued actions.
     pub fn cancel(self) {}
 
     /// Returns a queue.
-    pub fn queue(&mut self) -> Queue<C, F> {
+    pub fn queue(&mut self) -> Queue<'_, C, F> {
         self.record.queue()
     }
 
     /// Returns a checkpoint.
-    pub fn checkpoint(&mut self) -> Checkpoint<C, F> {
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, C, F> {
         self.record.checkpoint()
     }
 
@@ -628,23 +1311,87 @@ impl<'a, C: Command, F> From<&'a mut Record<C, F>> for Queue<'a, C, F> {
 
 #[derive(Debug)]
 enum CheckpointCommand<C> {
-    Apply(Option<usize>, VecDeque<Entry<C>>),
+    Apply(Option<usize>, BTreeMap<String, usize>, VecDeque<Entry<C>>),
     Undo,
     Redo,
 }
 
 /// Wraps a record and gives it checkpoint functionality.
-pub struct Checkpoint<'a, C: Command, F> {
+///
+/// If the checkpoint is dropped without calling [`commit`] the changes are canceled,
+/// unless it was created using [`commit_on_drop`].
+/// Commands applied through the checkpoint are not merged with other commands,
+/// use [`commit_squashed`] to merge them when committing.
+/// No commands are removed from the record because of its limit, retention, or budget
+/// while the checkpoint is open, so the changes can always be canceled.
+/// The commands are instead removed when the checkpoint is committed.
+///
+/// # Examples
+/// ```
+/// # use redo::{Command, Record};
+/// # struct Add(char);
+/// # impl Command for Add {
+/// #     type Target = String;
+/// #     type Error = &'static str;
+/// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         s.push(self.0);
+/// #         Ok(())
+/// #     }
+/// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         self.0 = s.pop().ok_or("s is empty")?;
+/// #         Ok(())
+/// #     }
+/// # }
+/// # fn main() -> redo::Result<Add> {
+/// let mut record = Record::default();
+/// let mut checkpoint = record.checkpoint();
+/// checkpoint.apply(Add('a'))?;
+/// checkpoint.apply(Add('b'))?;
+/// assert_eq!(checkpoint.target(), "ab");
+/// drop(checkpoint);
+/// assert_eq!(record.target(), "");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`commit`]: struct.Checkpoint.html#method.commit
+/// [`commit_on_drop`]: struct.Checkpoint.html#method.commit_on_drop
+/// [`commit_squashed`]: struct.Checkpoint.html#method.commit_squashed
+pub struct Checkpoint<'a, C: Command, F: Observer> {
     record: &'a mut Record<C, F>,
     commands: Vec<CheckpointCommand<C>>,
+    low: usize,
+    label: Option<String>,
+    cancel_on_drop: bool,
+    on_drop_error: Option<Box<dyn FnOnce(C::Error) + 'a>>,
+}
+
+impl<'a, C: Command, F: Observer> Checkpoint<'a, C, F> {
+    /// Returns a checkpoint that keeps the changes if it is dropped without being committed or canceled.
+    pub fn commit_on_drop(record: &'a mut Record<C, F>) -> Checkpoint<'a, C, F> {
+        let mut checkpoint = Checkpoint::from(record);
+        checkpoint.cancel_on_drop = false;
+        checkpoint
+    }
+
+    /// Sets the function that is called with the error if canceling the changes fails
+    /// when the checkpoint is dropped.
+    pub fn on_drop_error(&mut self, f: impl FnOnce(C::Error) + 'a) {
+        self.on_drop_error = Some(Box::new(f));
+    }
 }
 
-impl<C: Command, F: FnMut(Signal)> Checkpoint<'_, C, F> {
+impl<C: Command, F: Observer> Checkpoint<'_, C, F> {
     /// Calls the `apply` method.
     pub fn apply(&mut self, command: C) -> Result<C> {
         let saved = self.record.saved;
-        let (_, tail) = self.record.__apply(command)?;
-        self.commands.push(CheckpointCommand::Apply(saved, tail));
+        let bookmarks = self.record.bookmarks.clone();
+        let current = self.record.current();
+        // Commands are not merged so each apply can be rolled back by removing its own entry.
+        let Applied { tail, .. } = self.record.__apply(command, false)?;
+        self.low = self.low.min(current);
+        self.commands
+            .push(CheckpointCommand::Apply(saved, bookmarks, tail));
         Ok(())
     }
 
@@ -667,36 +1414,85 @@ impl<C: Command, F: FnMut(Signal)> Checkpoint<'_, C, F> {
     }
 
     /// Commits the changes and consumes the checkpoint.
-    pub fn commit(self) {}
-
-    /// Cancels the changes and consumes the checkpoint.
     ///
-    /// # Errors
-    /// If an error occur when canceling the changes, the error is returned
-    /// and the remaining commands are not canceled.
-    pub fn cancel(self) -> Result<C> {
-        for command in self.commands.into_iter().rev() {
-            match command {
-                CheckpointCommand::Apply(saved, mut entries) => {
-                    self.record.undo()?;
-                    self.record.entries.pop_back();
-                    self.record.entries.append(&mut entries);
-                    self.record.saved = saved;
-                }
-                CheckpointCommand::Undo => self.record.redo()?,
-                CheckpointCommand::Redo => self.record.undo()?,
+    /// If the checkpoint has a label and the current command was applied through the checkpoint,
+    /// the label is given to the command and the command is sealed.
+    pub fn commit(mut self) {
+        self.commands.clear();
+        if let Some(label) = self.label.take() {
+            if self.record.current() > self.low {
+                self.record.set_label(label);
             }
         }
-        Ok(())
     }
 
-    /// Returns a queue.
-    pub fn queue(&mut self) -> Queue<C, F> {
-        self.record.queue()
-    }
+    /// Commits the changes as a single entry in the record and consumes the checkpoint.
+    ///
+    /// The applied commands are merged using their [`merge`] method if possible,
+    /// otherwise they are stored in a [`Group`].
+    /// If the checkpoint has a label, the label is given to the entry and the entry is sealed.
+    ///
+    /// [`merge`]: ../trait.Command.html#method.merge
+    /// [`Group`]: ../struct.Group.html
+    pub fn commit_squashed(mut self)
+    where
+        C: From<Group<C>>,
+    {
+        self.commands.clear();
+        let label = self.label.take();
+        self.record.squash(self.low, label);
+    }
+
+    /// Sets the label that is given to the entry created by [`commit_squashed`],
+    /// or to the last command applied through the checkpoint by [`commit`].
+    ///
+    /// The label is shown instead of the text of the command
+    /// by [`undo_text`], [`redo_text`], and the display structure.
+    ///
+    /// [`commit`]: struct.Checkpoint.html#method.commit
+    /// [`commit_squashed`]: struct.Checkpoint.html#method.commit_squashed
+    /// [`undo_text`]: struct.Record.html#method.undo_text
+    /// [`redo_text`]: struct.Record.html#method.redo_text
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
+    }
+
+    /// Cancels the changes and consumes the checkpoint.
+    ///
+    /// # Errors
+    /// If an error occur when canceling the changes, the error is returned
+    /// and the remaining commands are not canceled.
+    pub fn cancel(mut self) -> Result<C> {
+        self.rollback()
+    }
+
+    fn rollback(&mut self) -> Result<C> {
+        let commands = mem::take(&mut self.commands);
+        self.record.batch(|record| {
+            for command in commands.into_iter().rev() {
+                match command {
+                    CheckpointCommand::Apply(saved, bookmarks, mut entries) => {
+                        record.undo()?;
+                        record.entries.pop_back();
+                        record.entries.append(&mut entries);
+                        record.saved = saved;
+                        record.bookmarks = bookmarks;
+                    }
+                    CheckpointCommand::Undo => record.redo()?,
+                    CheckpointCommand::Redo => record.undo()?,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns a queue.
+    pub fn queue(&mut self) -> Queue<'_, C, F> {
+        self.record.queue()
+    }
 
     /// Returns a checkpoint.
-    pub fn checkpoint(&mut self) -> Checkpoint<C, F> {
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, C, F> {
         self.record.checkpoint()
     }
 
@@ -706,11 +1502,29 @@ impl<C: Command, F: FnMut(Signal)> Checkpoint<'_, C, F> {
     }
 }
 
-impl<'a, C: Command, F> From<&'a mut Record<C, F>> for Checkpoint<'a, C, F> {
+impl<C: Command, F: Observer> Drop for Checkpoint<'_, C, F> {
+    fn drop(&mut self) {
+        if self.cancel_on_drop {
+            if let Err(err) = self.rollback() {
+                if let Some(f) = self.on_drop_error.take() {
+                    f(err);
+                }
+            }
+        }
+        self.record.end_checkpoint();
+    }
+}
+
+impl<'a, C: Command, F: Observer> From<&'a mut Record<C, F>> for Checkpoint<'a, C, F> {
     fn from(record: &'a mut Record<C, F>) -> Self {
+        record.begin_checkpoint();
         Checkpoint {
+            low: record.current(),
             record,
             commands: Vec::new(),
+            label: None,
+            cancel_on_drop: true,
+            on_drop_error: None,
         }
     }
 }
@@ -722,7 +1536,7 @@ pub struct Display<'a, C: Command, F> {
     format: crate::format::Format,
 }
 
-impl<C: Command, F: FnMut(Signal)> Display<'_, C, F> {
+impl<C: Command, F: Observer> Display<'_, C, F> {
     /// Show colored output (on by default).
     ///
     /// Requires the `colored` feature to be enabled.
@@ -812,7 +1626,8 @@ impl<C: Command + fmt::Display, F> fmt::Display for Display<'_, C, F> {
 #[cfg(test)]
 mod tests {
     use crate::*;
-    use alloc::string::String;
+    use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
+    use core::cell::{Cell, RefCell};
 
     struct Add(char);
 
@@ -821,6 +1636,9 @@ mod tests {
         type Error = &'static str;
 
         fn apply(&mut self, s: &mut String) -> Result<Add> {
+            if self.0 == '!' {
+                return Err("invalid char");
+            }
             s.push(self.0);
             Ok(())
         }
@@ -886,6 +1704,374 @@ mod tests {
         assert_eq!(record.target(), "abc");
     }
 
+    #[test]
+    fn queue_rollback() {
+        let mut record = Record::default();
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        let mut queue = record.queue();
+        queue.undo();
+        queue.apply(Add('c'));
+        queue.apply(Add('d'));
+        queue.apply(Add('!'));
+        queue.apply(Add('e'));
+        let err = queue.commit().unwrap_err();
+        assert_eq!(err.index, 3);
+        assert_eq!(err.error, "invalid char");
+        assert_eq!(record.target(), "ab");
+        assert_eq!(record.current(), 2);
+        assert_eq!(record.len(), 2);
+    }
+
+    #[test]
+    fn transaction_commit() {
+        let mut record = Record::default();
+        let mut transaction = record.transaction("ab");
+        transaction.apply(Push("a".into())).unwrap();
+        transaction.apply(Push("b".into())).unwrap();
+        transaction.commit();
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.get(1).unwrap().label(), Some("ab"));
+        // The labeled entry is sealed.
+        record.apply(Push("c".into())).unwrap();
+        assert_eq!(record.len(), 3);
+        // Nothing is labeled if no commands were applied.
+        let mut transaction = record.transaction("undo");
+        transaction.undo().unwrap();
+        transaction.commit();
+        assert_eq!(record.get(2).unwrap().label(), None);
+    }
+
+    #[test]
+    fn checkpoint_limit() {
+        let mut record = record::Builder::new().limit(2).default();
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        record.set_saved(true);
+        record.set_bookmark("x");
+        let mut cp = record.checkpoint();
+        cp.apply(Add('c')).unwrap();
+        assert_eq!(cp.target(), "abc");
+        cp.cancel().unwrap();
+        assert_eq!(record.target(), "ab");
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.current(), 2);
+        assert!(record.is_saved());
+        assert!(record.is_at_bookmark("x"));
+        // The commands are evicted when the checkpoint is committed.
+        let mut cp = record.checkpoint();
+        cp.apply(Add('c')).unwrap();
+        cp.apply(Add('d')).unwrap();
+        cp.commit();
+        assert_eq!(record.target(), "abcd");
+        assert_eq!(record.len(), 2);
+        record.undo().unwrap();
+        record.undo().unwrap();
+        assert_eq!(record.target(), "ab");
+        assert!(record.is_saved());
+        assert!(!record.can_undo());
+    }
+
+    #[test]
+    fn checkpoint_drop_merge() {
+        let mut record = Record::default();
+        record.apply(Sum(5)).unwrap();
+        record.seal();
+        record.apply(Sum(1)).unwrap();
+        let mut cp = record.checkpoint();
+        cp.apply(Sum(-1)).unwrap();
+        assert_eq!(*cp.target(), 5);
+        drop(cp);
+        assert_eq!(*record.target(), 6);
+        assert_eq!(record.len(), 2);
+        let mut cp = record.checkpoint();
+        cp.apply(Sum(2)).unwrap();
+        cp.apply(Sum(3)).unwrap();
+        drop(cp);
+        assert_eq!(*record.target(), 6);
+        assert_eq!(record.len(), 2);
+    }
+
+    #[test]
+    fn queue_rollback_limit() {
+        let mut record = record::Builder::new().limit(2).default();
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        record.set_saved(true);
+        record.set_bookmark("x");
+        let mut queue = record.queue();
+        queue.apply(Add('c'));
+        queue.apply(Add('d'));
+        queue.apply(Add('!'));
+        assert_eq!(queue.commit().unwrap_err().index, 2);
+        assert_eq!(record.target(), "ab");
+        assert_eq!(record.len(), 2);
+        assert!(record.is_saved());
+        assert!(record.is_at_bookmark("x"));
+        record.undo().unwrap();
+        record.undo().unwrap();
+        assert_eq!(record.target(), "");
+    }
+
+    #[test]
+    fn checkpoint_drop_limit() {
+        let mut record = record::Builder::new().limit(1).default();
+        record.apply(Sum(1)).unwrap();
+        record.set_saved(true);
+        {
+            let mut cp = record.checkpoint();
+            cp.apply(Sum(2)).unwrap();
+            cp.apply(Sum(3)).unwrap();
+            assert_eq!(*cp.target(), 6);
+        }
+        assert_eq!(*record.target(), 1);
+        assert_eq!(record.len(), 1);
+        assert!(record.is_saved());
+        {
+            let mut cp = record::Checkpoint::commit_on_drop(&mut record);
+            cp.apply(Sum(2)).unwrap();
+            cp.apply(Sum(3)).unwrap();
+        }
+        assert_eq!(*record.target(), 6);
+        assert_eq!(record.len(), 1);
+        record.undo().unwrap();
+        assert_eq!(*record.target(), 3);
+        assert!(!record.can_undo());
+    }
+
+    #[test]
+    fn queue_rollback_merge() {
+        let mut record = Record::default();
+        record.apply(Sum(5)).unwrap();
+        let mut queue = record.queue();
+        queue.apply(Sum(1));
+        queue.apply(Sum(0));
+        assert_eq!(queue.commit().unwrap_err().index, 1);
+        assert_eq!(*record.target(), 5);
+        assert_eq!(record.len(), 1);
+        // The queued command would annul the current command if it was merged.
+        let mut queue = record.queue();
+        queue.apply(Sum(-5));
+        queue.apply(Sum(0));
+        assert_eq!(queue.commit().unwrap_err().index, 1);
+        assert_eq!(*record.target(), 5);
+        assert_eq!(record.len(), 1);
+        // Merging still works outside of queues and checkpoints.
+        record.apply(Sum(-5)).unwrap();
+        assert_eq!(*record.target(), 0);
+        assert_eq!(record.len(), 0);
+    }
+
+    #[test]
+    fn subscriptions() {
+        let a = Cell::new(0);
+        let b = Cell::new(0);
+        let mut record = record::Builder::new().build_with(
+            String::new(),
+            Box::new(|_| a.set(a.get() + 1)) as Box<dyn FnMut(Signal)>,
+        );
+        let subscription = record.connect(Box::new(|_| b.set(b.get() + 1)));
+        record.apply(Add('a')).unwrap();
+        assert_eq!(a.get(), 3);
+        assert_eq!(b.get(), 3);
+        assert!(record.disconnect(subscription).is_some());
+        assert!(record.disconnect(subscription).is_none());
+        record.undo().unwrap();
+        assert_eq!(a.get(), 7);
+        assert_eq!(b.get(), 3);
+    }
+
+    #[derive(Default)]
+    struct Outline {
+        applied: Vec<usize>,
+        undone: Vec<usize>,
+    }
+
+    impl Observer for &mut Outline {
+        fn on_apply(&mut self, index: usize) {
+            self.applied.push(index);
+        }
+
+        fn on_undo(&mut self, index: usize) {
+            self.undone.push(index);
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let mut outline = Outline::default();
+        let mut record = record::Builder::new().build_with(String::new(), &mut outline);
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        record.undo().unwrap();
+        drop(record);
+        assert_eq!(outline.applied, [0, 1]);
+        assert_eq!(outline.undone, [1]);
+    }
+
+    struct Count(Rc<Cell<usize>>);
+
+    impl Observer for Count {
+        fn on_apply(&mut self, _: usize) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn boxed_observers() {
+        let applied = Rc::new(Cell::new(0));
+        let signals = Rc::new(RefCell::new(Vec::new()));
+        let mut record = record::Builder::new().build_with(
+            String::new(),
+            Box::new(Count(Rc::clone(&applied))) as Box<dyn Observer>,
+        );
+        let s = Rc::clone(&signals);
+        record.connect(Box::new(move |signal| s.borrow_mut().push(signal)));
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        assert_eq!(applied.get(), 2);
+        assert_eq!(signals.borrow().len(), 4);
+    }
+
+    #[test]
+    fn signals() {
+        let signals = RefCell::new(Vec::new());
+        let take = || signals.borrow_mut().drain(..).collect::<Vec<_>>();
+        let mut record = record::Builder::new()
+            .limit(2)
+            .build_with(String::new(), |signal| signals.borrow_mut().push(signal));
+        record.apply(Push("a".into())).unwrap();
+        assert_eq!(
+            take(),
+            [Signal::Applied(0), Signal::Undo(true), Signal::Saved(false)]
+        );
+        record.apply(Push("b".into())).unwrap();
+        assert_eq!(take(), [Signal::Merged(0)]);
+        record.seal();
+        record.apply(Push("c".into())).unwrap();
+        assert_eq!(take(), [Signal::Applied(1)]);
+        record.seal();
+        record.apply(Push("d".into())).unwrap();
+        assert_eq!(take(), [Signal::Evicted(1), Signal::Applied(1)]);
+        record.undo().unwrap();
+        assert_eq!(take(), [Signal::Undone(1), Signal::Redo(true)]);
+        record.redo().unwrap();
+        assert_eq!(take(), [Signal::Redone(1), Signal::Redo(false)]);
+
+        let mut record = record::Builder::new()
+            .build_with(String::new(), |signal| signals.borrow_mut().push(signal));
+        record.apply(Annul).unwrap();
+        record.apply(Annul).unwrap();
+        assert_eq!(
+            take(),
+            [
+                Signal::Applied(0),
+                Signal::Undo(true),
+                Signal::Saved(false),
+                Signal::Annulled(0),
+                Signal::Undo(false),
+                Signal::Saved(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn batch() {
+        let signals = RefCell::new(Vec::new());
+        let take = || signals.borrow_mut().drain(..).collect::<Vec<_>>();
+        let mut record = record::Builder::new()
+            .build_with(String::new(), |signal| signals.borrow_mut().push(signal));
+        let mut queue = record.queue();
+        queue.apply(Add('a'));
+        queue.apply(Add('b'));
+        queue.undo();
+        queue.commit().unwrap();
+        assert_eq!(
+            take(),
+            [
+                Signal::Applied(0),
+                Signal::Undo(true),
+                Signal::Redo(true),
+                Signal::Saved(false),
+            ]
+        );
+        let mut cp = record.checkpoint();
+        cp.redo().unwrap();
+        cp.apply(Add('c')).unwrap();
+        take();
+        cp.cancel().unwrap();
+        assert_eq!(take(), [Signal::Redo(true)]);
+        assert_eq!(record.target(), "a");
+        record.go_to(0).unwrap().unwrap();
+        assert_eq!(take(), [Signal::Undo(false), Signal::Saved(true)]);
+
+        let mut record = record::Builder::new()
+            .limit(1)
+            .build_with(String::new(), |signal| signals.borrow_mut().push(signal));
+        let mut queue = record.queue();
+        queue.apply(Add('a'));
+        queue.apply(Add('b'));
+        queue.apply(Add('c'));
+        queue.commit().unwrap();
+        assert_eq!(
+            take(),
+            [
+                Signal::Evicted(2),
+                Signal::Applied(0),
+                Signal::Undo(true),
+                Signal::Saved(false),
+            ]
+        );
+    }
+
+    #[test]
+    fn bookmarks() {
+        let signals = RefCell::new(Vec::new());
+        let mut record = record::Builder::new()
+            .limit(3)
+            .build_with(String::new(), |signal| {
+                if let Signal::Bookmark(..) = signal {
+                    signals.borrow_mut().push(signal);
+                }
+            });
+        let take = || signals.borrow_mut().drain(..).collect::<Vec<_>>();
+        record.apply(Add('a')).unwrap();
+        record.set_bookmark("sync");
+        record.apply(Add('b')).unwrap();
+        record.set_bookmark("auto");
+        record.undo().unwrap();
+        assert_eq!(
+            take(),
+            [
+                Signal::Bookmark("sync".into(), true),
+                Signal::Bookmark("sync".into(), false),
+                Signal::Bookmark("auto".into(), true),
+                Signal::Bookmark("auto".into(), false),
+                Signal::Bookmark("sync".into(), true),
+            ]
+        );
+        // The bookmark after the current position is removed when a command is applied.
+        record.apply(Add('c')).unwrap();
+        assert_eq!(record.bookmark("auto"), None);
+        assert_eq!(record.bookmark("sync"), Some(1));
+        record.apply(Add('d')).unwrap();
+        record.apply(Add('e')).unwrap();
+        assert_eq!(record.bookmark("sync"), Some(0));
+        record.apply(Add('f')).unwrap();
+        assert_eq!(record.bookmark("sync"), None);
+        // The bookmark is restored when the checkpoint is canceled.
+        record.set_bookmark("auto");
+        record.undo().unwrap();
+        let mut cp = record.checkpoint();
+        cp.apply(Add('g')).unwrap();
+        cp.cancel().unwrap();
+        assert_eq!(record.bookmark("auto"), Some(3));
+        record.go_to_bookmark("auto").unwrap().unwrap();
+        assert!(record.is_at_bookmark("auto"));
+        assert_eq!(record.remove_bookmark("auto"), Some(3));
+        assert_eq!(record.bookmarks().count(), 0);
+    }
+
     #[test]
     fn checkpoint_commit() {
         let mut record = Record::default();
@@ -957,4 +2143,357 @@ mod tests {
         assert!(record.is_saved());
         assert_eq!(record.target(), "abc");
     }
+
+    #[test]
+    fn checkpoint_drop() {
+        let mut record = Record::default();
+        record.apply(Add('a')).unwrap();
+        {
+            let mut cp = record.checkpoint();
+            cp.apply(Add('b')).unwrap();
+            cp.apply(Add('c')).unwrap();
+            assert_eq!(cp.target(), "abc");
+        }
+        assert_eq!(record.target(), "a");
+        assert_eq!(record.len(), 1);
+        {
+            let mut cp = record::Checkpoint::commit_on_drop(&mut record);
+            cp.apply(Add('b')).unwrap();
+        }
+        assert_eq!(record.target(), "ab");
+        // The undo pops the '!' so redoing the command fails when the checkpoint is dropped.
+        record.target_mut().push('!');
+        let mut error = None;
+        {
+            let mut cp = record.checkpoint();
+            cp.on_drop_error(|err| error = Some(err));
+            cp.undo().unwrap();
+        }
+        assert_eq!(error, Some("invalid char"));
+    }
+
+    #[test]
+    fn checkpoint_squash() {
+        let mut record = Record::default();
+        record.apply(BoxedCommand::new(Add('a'))).unwrap();
+        let mut cp = record.checkpoint();
+        cp.apply(BoxedCommand::new(Add('b'))).unwrap();
+        cp.apply(BoxedCommand::new(Add('c'))).unwrap();
+        cp.apply(BoxedCommand::new(Add('d'))).unwrap();
+        cp.commit_squashed();
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.current(), 2);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "a");
+        record.redo().unwrap();
+        assert_eq!(record.target(), "abcd");
+    }
+
+    struct Sum(i32);
+
+    impl Command for Sum {
+        type Target = i32;
+        type Error = &'static str;
+
+        fn apply(&mut self, n: &mut i32) -> Result<Sum> {
+            if self.0 == 0 {
+                return Err("zero");
+            }
+            *n += self.0;
+            Ok(())
+        }
+
+        fn undo(&mut self, n: &mut i32) -> Result<Sum> {
+            *n -= self.0;
+            Ok(())
+        }
+
+        fn merge(&mut self, Sum(n): Self) -> Merge<Self> {
+            if self.0 + n == 0 {
+                Merge::Annul
+            } else {
+                self.0 += n;
+                Merge::Yes
+            }
+        }
+    }
+
+    struct Push(String);
+
+    impl Command for Push {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Push> {
+            s.push_str(&self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Push> {
+            s.truncate(s.len() - self.0.len());
+            Ok(())
+        }
+
+        fn merge(&mut self, Push(s): Self) -> Merge<Self> {
+            self.0.push_str(&s);
+            Merge::Yes
+        }
+
+        fn size_hint(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    struct Annul;
+
+    impl Command for Annul {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, _: &mut String) -> Result<Annul> {
+            Ok(())
+        }
+
+        fn undo(&mut self, _: &mut String) -> Result<Annul> {
+            Ok(())
+        }
+
+        fn merge(&mut self, _: Self) -> Merge<Self> {
+            Merge::Annul
+        }
+    }
+
+    #[test]
+    fn bookmark_merge() {
+        let mut record = Record::default();
+        record.set_saved(false);
+        record.apply(Push("a".into())).unwrap();
+        record.set_bookmark("sync");
+        record.apply(Push("b".into())).unwrap();
+        record.apply(Push("c".into())).unwrap();
+        assert_eq!(record.len(), 2);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "a");
+    }
+
+    #[test]
+    fn break_merge() {
+        let mut record = Record::default();
+        record.set_saved(false);
+        record.apply(Push("a".into())).unwrap();
+        record.apply(Push("b".into())).unwrap();
+        record.break_merge();
+        record.apply(Push("c".into())).unwrap();
+        record.apply(Push("d".into())).unwrap();
+        assert_eq!(record.len(), 2);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "ab");
+    }
+
+    #[test]
+    fn seal() {
+        let mut record = Record::default();
+        record.set_saved(false);
+        record.apply(Push("a".into())).unwrap();
+        record.apply(Push("b".into())).unwrap();
+        record.seal();
+        record.apply(Push("c".into())).unwrap();
+        record.apply(Push("d".into())).unwrap();
+        assert_eq!(record.len(), 2);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "ab");
+        // The seal is kept when the command is undone and redone.
+        record.undo().unwrap();
+        record.redo().unwrap();
+        record.apply(Push("e".into())).unwrap();
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.target(), "abe");
+    }
+
+    #[test]
+    fn budget() {
+        let evicted = Cell::new(0);
+        let mut record = record::Builder::new()
+            .budget(4)
+            .build_with(String::new(), |signal| {
+                if let Signal::Evicted(n) = signal {
+                    evicted.set(evicted.get() + n);
+                }
+            });
+        record.apply(Push("ab".into())).unwrap();
+        record.seal();
+        record.apply(Push("c".into())).unwrap();
+        record.seal();
+        record.apply(Push("d".into())).unwrap();
+        assert_eq!(record.size(), 4);
+        assert_eq!(evicted.get(), 0);
+        // Merging into the current command can exceed the budget too.
+        record.apply(Push("e".into())).unwrap();
+        assert_eq!(record.size(), 3);
+        assert_eq!(record.len(), 2);
+        assert_eq!(evicted.get(), 1);
+        // The current command is kept even if it is larger than the budget.
+        record.seal();
+        record.apply(Push("fghij".into())).unwrap();
+        assert_eq!(record.len(), 1);
+        assert_eq!(evicted.get(), 3);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "abcde");
+        assert!(!record.can_undo());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn merge_interval() {
+        use chrono::Duration;
+
+        let mut record = record::Builder::new()
+            .saved(false)
+            .merge_interval(Duration::seconds(1))
+            .default();
+        record.apply(Push("a".into())).unwrap();
+        record.apply(Push("b".into())).unwrap();
+        assert_eq!(record.len(), 1);
+        record.entries[0].timestamp -= Duration::seconds(2);
+        record.apply(Push("c".into())).unwrap();
+        assert_eq!(record.len(), 2);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "ab");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn time_travel() {
+        use chrono::{Duration, Utc};
+
+        let mut record = Record::default();
+        for c in "abc".chars() {
+            record.apply(Add(c)).unwrap();
+        }
+        let start = Utc::now();
+        for (i, entry) in record.entries.iter_mut().enumerate() {
+            entry.timestamp = start + Duration::minutes(i as i64);
+        }
+        record
+            .time_travel(&(start + Duration::seconds(90)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.target(), "ab");
+        record
+            .time_travel(&(start - Duration::minutes(1)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.target(), "");
+        record
+            .time_travel(&(start + Duration::minutes(5)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.target(), "abc");
+        // An exact match goes to the state before the command.
+        record
+            .time_travel(&(start + Duration::minutes(1)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.target(), "a");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn travel() {
+        use chrono::{Duration, Utc};
+
+        let mut record = Record::default();
+        for c in "abcde".chars() {
+            record.apply(Add(c)).unwrap();
+        }
+        let start = Utc::now();
+        for (i, entry) in record.entries.iter_mut().enumerate() {
+            entry.timestamp = start + Duration::minutes(i as i64);
+        }
+        assert_eq!(record.travel_back(Duration::seconds(150)), Ok(3));
+        assert_eq!(record.target(), "ab");
+        assert_eq!(record.travel_forward(Duration::minutes(1)), Ok(1));
+        assert_eq!(record.target(), "abc");
+        assert_eq!(record.travel_back(Duration::minutes(10)), Ok(3));
+        assert_eq!(record.target(), "");
+        assert_eq!(record.travel_back(Duration::minutes(10)), Ok(0));
+        assert_eq!(record.travel_forward(Duration::zero()), Ok(1));
+        assert_eq!(record.target(), "a");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn zero_retention() {
+        use chrono::Duration;
+
+        let mut record = record::Builder::new().retention(Duration::zero()).default();
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        assert_eq!(record.len(), 1);
+        assert_eq!(record.current(), 1);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "a");
+        assert!(!record.can_undo());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn zero_retention_signals() {
+        use chrono::Duration;
+
+        let signals = RefCell::new(Vec::new());
+        let take = || signals.borrow_mut().drain(..).collect::<Vec<_>>();
+        let mut record = record::Builder::new()
+            .retention(Duration::zero())
+            .build_with(0, |signal| signals.borrow_mut().push(signal));
+        record.apply(Sum(1)).unwrap();
+        record.apply(Sum(2)).unwrap();
+        record.seal();
+        record.apply(Sum(3)).unwrap();
+        record.apply(Sum(-3)).unwrap();
+        assert_eq!(
+            take(),
+            [
+                Signal::Applied(0),
+                Signal::Undo(true),
+                Signal::Saved(false),
+                Signal::Merged(0),
+                Signal::Evicted(1),
+                Signal::Applied(0),
+                Signal::Annulled(0),
+                Signal::Undo(false),
+            ]
+        );
+        assert_eq!(*record.target(), 3);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn prune_older_than() {
+        use chrono::{Duration, Utc};
+
+        let mut record = record::Builder::new()
+            .retention(Duration::minutes(10))
+            .default();
+        record.apply(Add('a')).unwrap();
+        record.apply(Add('b')).unwrap();
+        record.apply(Add('c')).unwrap();
+        record.entries[0].timestamp -= Duration::minutes(20);
+        record.entries[1].timestamp -= Duration::minutes(5);
+        record.undo().unwrap();
+        record.apply(Add('d')).unwrap();
+        assert_eq!(record.len(), 2);
+        assert!(!record.is_saved());
+        assert_eq!(
+            record.prune_older_than(&(Utc::now() - Duration::minutes(1))),
+            1
+        );
+        assert_eq!(record.len(), 1);
+        record.undo().unwrap();
+        assert_eq!(record.target(), "ab");
+        assert!(!record.can_undo());
+        // Commands that can be redone are kept.
+        assert_eq!(record.prune_older_than(&Utc::now()), 0);
+        assert!(record.can_redo());
+    }
 }
@@ -0,0 +1,689 @@
+use crate::{Command, History, Message, Observer, Record, Result, Signal, Slot, Subscription};
+use alloc::vec::Vec;
+use core::{fmt, mem, ops::Deref};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+
+/// The slot of the record or history inside a shared record or history.
+///
+/// It collects the messages emitted while the lock is held, so they can be delivered
+/// to the slots of the shared record or history after the lock is released.
+///
+/// Requires the `std` feature to be enabled.
+#[derive(Debug)]
+pub struct Pending {
+    messages: Vec<Message>,
+}
+
+impl Pending {
+    fn new() -> Pending {
+        Pending {
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl Observer for Pending {
+    fn receive(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+}
+
+/// A record or history that collects its messages in a `Pending` slot.
+trait Inner {
+    fn slot(&mut self) -> &mut Slot<Pending>;
+
+    /// Returns the pending messages.
+    fn pending(&mut self) -> &mut Vec<Message> {
+        &mut self.slot().fs[0].1.messages
+    }
+}
+
+impl<C: Command> Inner for Record<C, Pending> {
+    fn slot(&mut self) -> &mut Slot<Pending> {
+        &mut self.slot
+    }
+}
+
+impl<C: Command> Inner for History<C, Pending> {
+    fn slot(&mut self) -> &mut Slot<Pending> {
+        &mut self.record.slot
+    }
+}
+
+fn pending_slot() -> Slot<Pending> {
+    let mut slot = Slot::default();
+    slot.connect(Pending::new());
+    slot
+}
+
+struct Shared<T, F> {
+    inner: Mutex<T>,
+    slot: Mutex<Slot<F>>,
+}
+
+impl<T: Inner, F> Shared<T, F> {
+    fn new(inner: T, slot: Slot<F>) -> Arc<Shared<T, F>> {
+        Arc::new(Shared {
+            inner: Mutex::new(inner),
+            slot: Mutex::new(slot),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        // The record or history can still be used after a panic in a command,
+        // although the target may have been left partially modified.
+        self.inner.lock().unwrap_or_else(|err| {
+            let mut inner = err.into_inner();
+            // The slot is not given back if the panic happened in an operation
+            // that emits its signals when it is done, e.g. `go_to`.
+            let slot = inner.slot();
+            if slot.is_empty() {
+                slot.connect(Pending::new());
+            }
+            inner
+        })
+    }
+
+    fn slot(&self) -> MutexGuard<'_, Slot<F>> {
+        // A panic in a slot does not leave the record or history in an inconsistent state.
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Inner, F: Observer> Shared<T, F> {
+    /// Delivers the pending messages to the slots without holding the lock on the inner value.
+    ///
+    /// If the slots are already in use, by another thread or by a slot calling into the shared value,
+    /// the messages are delivered by the call that is using them.
+    fn deliver(&self) {
+        loop {
+            let mut slot = match self.slot.try_lock() {
+                Ok(slot) => slot,
+                Err(TryLockError::Poisoned(err)) => err.into_inner(),
+                Err(TryLockError::WouldBlock) => return,
+            };
+            loop {
+                let messages = mem::take(self.lock().pending());
+                if messages.is_empty() {
+                    break;
+                }
+                for message in messages {
+                    slot.emit(message);
+                }
+            }
+            drop(slot);
+            // Messages added after the last check but before the slots were released
+            // were not delivered by the call that added them.
+            if self.lock().pending().is_empty() {
+                return;
+            }
+        }
+    }
+}
+
+enum Guard<'a, C: Command> {
+    Record(MutexGuard<'a, Record<C, Pending>>),
+    History(MutexGuard<'a, History<C, Pending>>),
+}
+
+/// A guard that gives read access to the target of a shared record or history.
+///
+/// The shared record or history is locked until the guard is dropped.
+/// Calling a method on the shared record or history on the same thread while holding the guard,
+/// e.g. `apply`, deadlocks.
+///
+/// Requires the `std` feature to be enabled.
+pub struct TargetGuard<'a, C: Command> {
+    guard: Guard<'a, C>,
+}
+
+impl<C: Command> Deref for TargetGuard<'_, C> {
+    type Target = C::Target;
+
+    fn deref(&self) -> &C::Target {
+        match self.guard {
+            Guard::Record(ref record) => record.target(),
+            Guard::History(ref history) => history.target(),
+        }
+    }
+}
+
+impl<C: Command> fmt::Debug for TargetGuard<'_, C>
+where
+    C::Target: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// A record that can be shared between threads.
+///
+/// Cloning a shared record returns a new handle to the same record.
+/// The signals are delivered to the slots after the lock on the record has been released,
+/// so the slots can read from the record without deadlocking.
+/// The shared record is [`Send`] and [`Sync`] if the commands, the target, and the slots are [`Send`].
+///
+/// Requires the `std` feature to be enabled.
+///
+/// # Examples
+/// ```
+/// # use redo::{Command, SharedRecord};
+/// # struct Add(char);
+/// # impl Command for Add {
+/// #     type Target = String;
+/// #     type Error = &'static str;
+/// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         s.push(self.0);
+/// #         Ok(())
+/// #     }
+/// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         self.0 = s.pop().ok_or("s is empty")?;
+/// #         Ok(())
+/// #     }
+/// # }
+/// # fn main() -> redo::Result<Add> {
+/// let record = SharedRecord::new(String::new());
+/// let shared = record.clone();
+/// std::thread::spawn(move || shared.apply(Add('a'))).join().unwrap()?;
+/// assert_eq!(*record.target(), "a");
+/// record.undo()?;
+/// assert_eq!(*record.target(), "");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Send`]: https://doc.rust-lang.org/std/marker/trait.Send.html
+/// [`Sync`]: https://doc.rust-lang.org/std/marker/trait.Sync.html
+pub struct SharedRecord<C: Command, F = fn(Signal)> {
+    shared: Arc<Shared<Record<C, Pending>, F>>,
+}
+
+impl<C: Command> SharedRecord<C> {
+    /// Returns a new shared record.
+    pub fn new(target: C::Target) -> SharedRecord<C> {
+        SharedRecord::from(Record::new(target))
+    }
+}
+
+impl<C: Command, F> SharedRecord<C, F> {
+    /// Returns a guard that gives read access to the target.
+    ///
+    /// The record is locked until the guard is dropped, so calling another method
+    /// on the record on the same thread while holding the guard deadlocks.
+    pub fn target(&self) -> TargetGuard<'_, C> {
+        TargetGuard {
+            guard: Guard::Record(self.shared.lock()),
+        }
+    }
+
+    /// Returns the number of commands in the record.
+    pub fn len(&self) -> usize {
+        self.shared.lock().len()
+    }
+
+    /// Returns `true` if the record is empty.
+    pub fn is_empty(&self) -> bool {
+        self.shared.lock().is_empty()
+    }
+
+    /// Returns the position of the current command.
+    pub fn current(&self) -> usize {
+        self.shared.lock().current()
+    }
+
+    /// Returns `true` if the record can undo.
+    pub fn can_undo(&self) -> bool {
+        self.shared.lock().can_undo()
+    }
+
+    /// Returns `true` if the record can redo.
+    pub fn can_redo(&self) -> bool {
+        self.shared.lock().can_redo()
+    }
+
+    /// Returns `true` if the target is in a saved state, `false` otherwise.
+    pub fn is_saved(&self) -> bool {
+        self.shared.lock().is_saved()
+    }
+
+    /// Connects a slot that is called with the signal when the state changes.
+    ///
+    /// See [`Record::connect`](record/struct.Record.html#method.connect).
+    /// Slots must not connect or disconnect slots themselves, since that would deadlock.
+    pub fn connect(&self, slot: F) -> Subscription {
+        self.shared.slot().connect(slot)
+    }
+
+    /// Removes and returns the slot with the subscription if it exists.
+    pub fn disconnect(&self, subscription: Subscription) -> Option<F> {
+        self.shared.slot().disconnect(subscription)
+    }
+}
+
+impl<C: Command, F: Observer> SharedRecord<C, F> {
+    /// Pushes the command on top of the record and executes its [`apply`] method.
+    ///
+    /// See [`Record::apply`](record/struct.Record.html#method.apply).
+    ///
+    /// [`apply`]: trait.Command.html#tymethod.apply
+    pub fn apply(&self, command: C) -> Result<C> {
+        let result = self.shared.lock().apply(command);
+        self.shared.deliver();
+        result
+    }
+
+    /// Calls the [`undo`] method for the active command.
+    ///
+    /// See [`Record::undo`](record/struct.Record.html#method.undo).
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    pub fn undo(&self) -> Result<C> {
+        let result = self.shared.lock().undo();
+        self.shared.deliver();
+        result
+    }
+
+    /// Calls the [`redo`] method for the active command.
+    ///
+    /// See [`Record::redo`](record/struct.Record.html#method.redo).
+    ///
+    /// [`redo`]: trait.Command.html#method.redo
+    pub fn redo(&self) -> Result<C> {
+        let result = self.shared.lock().redo();
+        self.shared.deliver();
+        result
+    }
+
+    /// Repeatedly calls [`undo`] or [`redo`] until the command at `current` is reached.
+    ///
+    /// See [`Record::go_to`](record/struct.Record.html#method.go_to).
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    /// [`redo`]: trait.Command.html#method.redo
+    pub fn go_to(&self, current: usize) -> Option<Result<C>> {
+        let result = self.shared.lock().go_to(current);
+        self.shared.deliver();
+        result
+    }
+
+    /// Calls `f` with mutable access to the record, e.g. to call [`set_saved`] or [`undo_text`],
+    /// and delivers the signals emitted by `f` after the lock on the record is released.
+    ///
+    /// The record is locked while `f` is called, so using the shared record from `f` deadlocks.
+    ///
+    /// [`set_saved`]: record/struct.Record.html#method.set_saved
+    /// [`undo_text`]: record/struct.Record.html#method.undo_text
+    pub fn with<T>(&self, f: impl FnOnce(&mut Record<C, Pending>) -> T) -> T {
+        let t = f(&mut self.shared.lock());
+        self.shared.deliver();
+        t
+    }
+}
+
+impl<C: Command, F> Clone for SharedRecord<C, F> {
+    fn clone(&self) -> Self {
+        SharedRecord {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<C: Command, F> From<Record<C, F>> for SharedRecord<C, F> {
+    fn from(record: Record<C, F>) -> Self {
+        let (record, slot) = record.replace_slot(pending_slot());
+        SharedRecord {
+            shared: Shared::new(record, slot),
+        }
+    }
+}
+
+impl<C: Command, F> fmt::Debug for SharedRecord<C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("SharedRecord { .. }")
+    }
+}
+
+/// A history that can be shared between threads.
+///
+/// Cloning a shared history returns a new handle to the same history.
+/// The signals are delivered to the slots after the lock on the history has been released,
+/// so the slots can read from the history without deadlocking.
+/// The shared history is [`Send`] and [`Sync`] if the commands, the target, and the slots are [`Send`].
+///
+/// Requires the `std` feature to be enabled.
+///
+/// [`Send`]: https://doc.rust-lang.org/std/marker/trait.Send.html
+/// [`Sync`]: https://doc.rust-lang.org/std/marker/trait.Sync.html
+pub struct SharedHistory<C: Command, F = fn(Signal)> {
+    shared: Arc<Shared<History<C, Pending>, F>>,
+}
+
+impl<C: Command> SharedHistory<C> {
+    /// Returns a new shared history.
+    pub fn new(target: C::Target) -> SharedHistory<C> {
+        SharedHistory::from(History::new(target))
+    }
+}
+
+impl<C: Command, F> SharedHistory<C, F> {
+    /// Returns a guard that gives read access to the target.
+    ///
+    /// The history is locked until the guard is dropped, so calling another method
+    /// on the history on the same thread while holding the guard deadlocks.
+    pub fn target(&self) -> TargetGuard<'_, C> {
+        TargetGuard {
+            guard: Guard::History(self.shared.lock()),
+        }
+    }
+
+    /// Returns the number of commands in the current branch of the history.
+    pub fn len(&self) -> usize {
+        self.shared.lock().len()
+    }
+
+    /// Returns `true` if the current branch of the history is empty.
+    pub fn is_empty(&self) -> bool {
+        self.shared.lock().is_empty()
+    }
+
+    /// Returns the current branch.
+    pub fn branch(&self) -> usize {
+        self.shared.lock().branch()
+    }
+
+    /// Returns the position of the current command.
+    pub fn current(&self) -> usize {
+        self.shared.lock().current()
+    }
+
+    /// Returns `true` if the history can undo.
+    pub fn can_undo(&self) -> bool {
+        self.shared.lock().can_undo()
+    }
+
+    /// Returns `true` if the history can redo.
+    pub fn can_redo(&self) -> bool {
+        self.shared.lock().can_redo()
+    }
+
+    /// Returns `true` if the target is in a saved state, `false` otherwise.
+    pub fn is_saved(&self) -> bool {
+        self.shared.lock().is_saved()
+    }
+
+    /// Connects a slot that is called with the signal when the state changes.
+    ///
+    /// See [`History::connect`](history/struct.History.html#method.connect).
+    /// Slots must not connect or disconnect slots themselves, since that would deadlock.
+    pub fn connect(&self, slot: F) -> Subscription {
+        self.shared.slot().connect(slot)
+    }
+
+    /// Removes and returns the slot with the subscription if it exists.
+    pub fn disconnect(&self, subscription: Subscription) -> Option<F> {
+        self.shared.slot().disconnect(subscription)
+    }
+}
+
+impl<C: Command, F: Observer> SharedHistory<C, F> {
+    /// Pushes the command to the top of the history and executes its [`apply`] method.
+    ///
+    /// See [`History::apply`](history/struct.History.html#method.apply).
+    ///
+    /// [`apply`]: trait.Command.html#tymethod.apply
+    pub fn apply(&self, command: C) -> Result<C> {
+        let result = self.shared.lock().apply(command);
+        self.shared.deliver();
+        result
+    }
+
+    /// Calls the [`undo`] method for the active command.
+    ///
+    /// See [`History::undo`](history/struct.History.html#method.undo).
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    pub fn undo(&self) -> Result<C> {
+        let result = self.shared.lock().undo();
+        self.shared.deliver();
+        result
+    }
+
+    /// Calls the [`redo`] method for the active command.
+    ///
+    /// See [`History::redo`](history/struct.History.html#method.redo).
+    ///
+    /// [`redo`]: trait.Command.html#method.redo
+    pub fn redo(&self) -> Result<C> {
+        let result = self.shared.lock().redo();
+        self.shared.deliver();
+        result
+    }
+
+    /// Repeatedly calls [`undo`] or [`redo`] until the command in `branch` at `current` is reached.
+    ///
+    /// See [`History::go_to`](history/struct.History.html#method.go_to).
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    /// [`redo`]: trait.Command.html#method.redo
+    pub fn go_to(&self, branch: usize, current: usize) -> Option<Result<C>> {
+        let result = self.shared.lock().go_to(branch, current);
+        self.shared.deliver();
+        result
+    }
+
+    /// Calls `f` with mutable access to the history, e.g. to call [`set_saved`] or [`undo_text`],
+    /// and delivers the signals emitted by `f` after the lock on the history is released.
+    ///
+    /// The history is locked while `f` is called, so using the shared history from `f` deadlocks.
+    ///
+    /// [`set_saved`]: history/struct.History.html#method.set_saved
+    /// [`undo_text`]: history/struct.History.html#method.undo_text
+    pub fn with<T>(&self, f: impl FnOnce(&mut History<C, Pending>) -> T) -> T {
+        let t = f(&mut self.shared.lock());
+        self.shared.deliver();
+        t
+    }
+}
+
+impl<C: Command, F> Clone for SharedHistory<C, F> {
+    fn clone(&self) -> Self {
+        SharedHistory {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<C: Command, F> From<History<C, F>> for SharedHistory<C, F> {
+    fn from(history: History<C, F>) -> Self {
+        let (history, slot) = history.replace_slot(pending_slot());
+        SharedHistory {
+            shared: Shared::new(history, slot),
+        }
+    }
+}
+
+impl<C: Command, F> fmt::Debug for SharedHistory<C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("SharedHistory { .. }")
+    }
+}
+
+// Checks that the shared record and history are `Send` and `Sync` when their parts are `Send`.
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[allow(dead_code)]
+    fn assert_shared<C: Command + Send, F: Send>()
+    where
+        C::Target: Send,
+    {
+        assert_send_sync::<SharedRecord<C, F>>();
+        assert_send_sync::<SharedHistory<C, F>>();
+    }
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::{
+        boxed::Box,
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread,
+    };
+
+    struct Add(char);
+
+    impl Command for Add {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Add> {
+            s.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Add> {
+            self.0 = s.pop().ok_or("s is empty")?;
+            Ok(())
+        }
+    }
+
+    type Slot = Box<dyn FnMut(Signal) + Send>;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<Record<Add>>();
+        assert_send_sync::<History<Add>>();
+        assert_send_sync::<SharedRecord<Add, Slot>>();
+        assert_send_sync::<SharedHistory<Add, Forward<mpsc::Sender<Message>>>>();
+    }
+
+    #[test]
+    fn threads() {
+        let (forward, receiver) = channel();
+        let history =
+            SharedHistory::from(history::Builder::new().build_with(String::new(), forward));
+        let handles: Vec<_> = ['a', 'b', 'c']
+            .iter()
+            .map(|&c| {
+                let history = history.clone();
+                thread::spawn(move || history.apply(Add(c)).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.target().len(), 3);
+        history.undo().unwrap();
+        let messages: Vec<_> = receiver.try_iter().collect();
+        let applied: Vec<_> = messages
+            .iter()
+            .filter_map(|message| match message.signal {
+                Signal::Applied(index) => Some(index),
+                _ => None,
+            })
+            .collect();
+        // The messages are delivered in the order they were emitted.
+        assert_eq!(applied, [0, 1, 2]);
+        let last = messages.last().unwrap();
+        assert_eq!((last.current, last.len), (2, 3));
+    }
+
+    /// Panics when applied if the char is not alphabetic, and when undone if it is uppercase.
+    struct Alpha(char);
+
+    impl Command for Alpha {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Alpha> {
+            assert!(self.0.is_alphabetic());
+            s.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Alpha> {
+            assert!(!self.0.is_uppercase());
+            self.0 = s.pop().ok_or("s is empty")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn poisoned() {
+        let record = SharedRecord::new(String::new());
+        let shared = record.clone();
+        let panicked = thread::spawn(move || shared.apply(Alpha('1'))).join();
+        assert!(panicked.is_err());
+        record.apply(Alpha('a')).unwrap();
+        assert_eq!(*record.target(), "a");
+        assert_eq!(record.len(), 1);
+    }
+
+    #[test]
+    fn poisoned_go_to() {
+        let (forward, receiver) = channel();
+        let history =
+            SharedHistory::from(history::Builder::new().build_with(String::new(), forward));
+        history.apply(Alpha('a')).unwrap();
+        history.apply(Alpha('B')).unwrap();
+        let shared = history.clone();
+        let panicked = thread::spawn(move || shared.go_to(0, 0)).join();
+        assert!(panicked.is_err());
+        receiver.try_iter().for_each(drop);
+        history.apply(Alpha('c')).unwrap();
+        assert_eq!(*history.target(), "aBc");
+        assert_eq!(history.len(), 3);
+        let applied: Vec<_> = receiver.try_iter().map(|message| message.signal).collect();
+        assert_eq!(applied, [Signal::Applied(2)]);
+    }
+
+    #[test]
+    fn with() {
+        let (forward, receiver) = channel();
+        let record = SharedRecord::from(record::Builder::new().build_with(String::new(), forward));
+        record.apply(Add('a')).unwrap();
+        receiver.try_iter().for_each(drop);
+        record.with(|record| {
+            record.set_saved(true);
+            record.set_bookmark("sync");
+        });
+        assert!(record.is_saved());
+        assert!(record.with(|record| record.is_at_bookmark("sync")));
+        let signals: Vec<_> = receiver.try_iter().map(|message| message.signal).collect();
+        assert_eq!(
+            signals,
+            [Signal::Saved(true), Signal::Bookmark("sync".into(), true)]
+        );
+    }
+
+    #[test]
+    fn reentrant() {
+        let record: SharedRecord<Add, Slot> = SharedRecord::from(
+            record::Builder::new().build_with(String::new(), Box::new(|_| {}) as Slot),
+        );
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let shared = record.clone();
+        let t = Arc::clone(&targets);
+        let subscription = record.connect(Box::new(move |signal| {
+            if let Signal::Applied(0) = signal {
+                // Using the record from a slot does not deadlock.
+                t.lock().unwrap().push(shared.target().to_string());
+                shared.apply(Add('b')).unwrap();
+            }
+        }));
+        record.apply(Add('a')).unwrap();
+        assert_eq!(*record.target(), "ab");
+        assert_eq!(*targets.lock().unwrap(), ["a"]);
+        // Breaks the reference cycle between the record and the slot.
+        assert!(record.disconnect(subscription).is_some());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Message, Observer};
+use std::sync::mpsc;
+
+/// A channel, queue, or similar that messages can be sent to.
+///
+/// Requires the `std` feature to be enabled.
+pub trait Sink {
+    /// Sends the message.
+    ///
+    /// Sending should not fail, e.g. a message sent after the receiver is gone should be dropped.
+    fn send(&mut self, message: Message);
+}
+
+impl Sink for mpsc::Sender<Message> {
+    fn send(&mut self, message: Message) {
+        let _ = mpsc::Sender::send(self, message);
+    }
+}
+
+/// Blocks until there is room in the channel.
+impl Sink for mpsc::SyncSender<Message> {
+    fn send(&mut self, message: Message) {
+        let _ = mpsc::SyncSender::send(self, message);
+    }
+}
+
+/// An observer that forwards the messages to a sink.
+///
+/// Requires the `std` feature to be enabled.
+///
+/// # Examples
+/// ```
+/// # use redo::{Command, Message, Signal, record::Builder};
+/// # struct Add(char);
+/// # impl Command for Add {
+/// #     type Target = String;
+/// #     type Error = &'static str;
+/// #     fn apply(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         s.push(self.0);
+/// #         Ok(())
+/// #     }
+/// #     fn undo(&mut self, s: &mut String) -> redo::Result<Add> {
+/// #         self.0 = s.pop().ok_or("s is empty")?;
+/// #         Ok(())
+/// #     }
+/// # }
+/// # fn main() -> Result<(), &'static str> {
+/// let (forward, receiver) = redo::channel();
+/// let mut record = Builder::new().build_with(String::new(), forward);
+/// std::thread::spawn(move || record.apply(Add('a'))).join().unwrap()?;
+/// let message = receiver.recv().unwrap();
+/// assert_eq!(message.signal, Signal::Applied(0));
+/// assert_eq!((message.current, message.len), (1, 1));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Forward<S> {
+    sink: S,
+}
+
+impl<S> Forward<S> {
+    /// Returns an observer that forwards the messages to `sink`.
+    pub fn new(sink: S) -> Forward<S> {
+        Forward { sink }
+    }
+
+    /// Returns a reference to the sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Consumes the observer, returning the sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: Sink> Observer for Forward<S> {
+    fn receive(&mut self, message: Message) {
+        self.sink.send(message);
+    }
+}
+
+/// Returns an observer that sends the messages on a new channel, and the receiving end of the channel.
+///
+/// Requires the `std` feature to be enabled.
+pub fn channel() -> (Forward<mpsc::Sender<Message>>, mpsc::Receiver<Message>) {
+    let (sender, receiver) = mpsc::channel();
+    (Forward::new(sender), receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::{string::String, vec::Vec};
+    use std::sync::mpsc;
+
+    struct Add(char);
+
+    impl Command for Add {
+        type Target = String;
+        type Error = &'static str;
+
+        fn apply(&mut self, s: &mut String) -> Result<Add> {
+            s.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, s: &mut String) -> Result<Add> {
+            self.0 = s.pop().ok_or("s is empty")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn forward() {
+        let (sender, receiver) = mpsc::channel();
+        let mut history = history::Builder::new().build_with(String::new(), Forward::new(sender));
+        history.apply(Add('a')).unwrap();
+        history.apply(Add('b')).unwrap();
+        history.undo().unwrap();
+        drop(history);
+        let undone: Vec<_> = receiver
+            .iter()
+            .filter(|message| message.signal == Signal::Undone(1))
+            .map(|message| (message.current, message.len))
+            .collect();
+        assert_eq!(undone, [(1, 2)]);
+    }
+}